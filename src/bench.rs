@@ -1,74 +1,389 @@
-use crate::config::PingThingsArgs;
-use crate::meteora::AccountsForBuy;
-use crate::tx_senders::transaction::TransactionConfig;
-use crate::tx_senders::{TxSender, create_tx_sender};
+use crate::config::{PingThingsArgs, RpcConfig, WalletsConfig, WsolMode};
+use crate::meteora::AmmSwap;
+use crate::policy::{Holdings, SharedHoldings, SharedSnipePolicy};
+use crate::priority_fee::{PriorityFeeOracle, SharedPriorityFeeOracle};
+use crate::tx_senders::constants::WSOL_MINT;
+use crate::tx_senders::nonce::SharedNoncePool;
+use crate::tx_senders::template::{Trade, TransactionTemplate};
+use crate::tx_senders::transaction::{BuyAccounts, PreparedInstructions, SellOrder, TransactionConfig};
+use crate::tx_senders::wallets::{Wallet, WalletPool};
+use crate::tx_senders::{BundleOutcome, TxResult, TxSender, create_tx_sender};
+use anyhow::{Context, bail};
+use futures::future::join_all;
 use reqwest::Client;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::transaction::VersionedTransaction;
+use spl_associated_token_account::get_associated_token_address;
+use std::collections::HashSet;
 use std::sync::Arc;
-use tracing::{error, info};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+const LANDING_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// past the blockhash validity, and as long as a nonce account is kept
+const LANDING_TIMEOUT: Duration = Duration::from_secs(90);
+
+// a trade handed to one sender
+#[derive(Clone)]
+pub struct SentTrade {
+    pub sender: Arc<dyn TxSender>,
+    // the wallet that signed it
+    pub wallet: Pubkey,
+    // the trade transaction, inside the bundle for bundling senders
+    pub signature: Signature,
+    pub result: TxResult,
+}
 
 #[derive(Clone)]
 pub struct Bench {
-    rpcs: Vec<Arc<dyn TxSender>>,
+    wallets: WalletPool,
+    // to rebuild the templates when the split changes
+    wallets_config: WalletsConfig,
+    senders: Arc<[(RpcConfig, Arc<dyn TxSender>)]>,
+    fee_oracle: Option<SharedPriorityFeeOracle>,
+    nonce_pool: Option<SharedNoncePool>,
+    // tells whether trades landed, None without http_rpc
+    rpc: Option<Arc<RpcClient>>,
+    // caps mirrored sells, kept up to date by the position tracker
+    holdings: SharedHoldings,
 }
 
 impl Bench {
-    pub fn new(config: PingThingsArgs) -> Self {
-        let tx_config: TransactionConfig = config.clone().into();
+    pub fn new(
+        config: PingThingsArgs,
+        lookup_tables: Vec<AddressLookupTableAccount>,
+        nonce_pool: Option<SharedNoncePool>,
+        wallets: Vec<Keypair>,
+    ) -> anyhow::Result<Self> {
+        let mut tx_config: TransactionConfig = config.clone().into();
+        tx_config.lookup_tables = lookup_tables;
         let client = Client::new();
 
-        let rpcs = config
+        let senders: Arc<[(RpcConfig, Arc<dyn TxSender>)]> = config
             .rpc
             .clone()
             .into_iter()
-            .map(|(name, rpc)| create_tx_sender(name, rpc, tx_config.clone(), client.clone()))
-            .collect::<Vec<Arc<dyn TxSender>>>();
+            .map(|(name, rpc)| Ok((rpc.clone(), create_tx_sender(name, rpc, client.clone())?)))
+            .collect::<anyhow::Result<_>>()?;
+
+        let wallet_pubkeys: HashSet<Pubkey> = wallets.iter().map(|keypair| keypair.pubkey()).collect();
+        let per_trade = WalletPool::per_trade(&config.wallets, wallets.len());
+        let wallets = wallets
+            .into_iter()
+            .map(|keypair| build_wallet(tx_config.for_wallet(Arc::new(keypair)), &senders, per_trade))
+            .collect();
+
+        let fee_oracle = config.priority_fee.enabled.then(|| {
+            PriorityFeeOracle::shared(
+                &config.priority_fee,
+                tx_config.static_fees(),
+                tx_config.tip_accounts.clone(),
+                wallet_pubkeys,
+            )
+        });
 
-        Bench { rpcs }
+        Ok(Bench {
+            wallets: WalletPool::new(wallets, per_trade),
+            wallets_config: config.wallets.clone(),
+            senders,
+            fee_oracle,
+            nonce_pool,
+            rpc: config.http_rpc_client().map(Arc::new),
+            holdings: Holdings::shared(),
+        })
+    }
+
+    // the most one buy can spend: the largest sender buy_amount, for every wallet of a split
+    pub fn max_buy_amount(&self) -> u64 {
+        let largest = self
+            .wallets
+            .wallets()
+            .iter()
+            .flat_map(|wallet| wallet.rpcs.iter().map(|(_, template)| template.tx_config().buy_amount))
+            .max()
+            .unwrap_or_default();
+        largest * self.wallets.shares() as u64
+    }
+
+    pub fn wallet_pubkeys(&self) -> HashSet<Pubkey> {
+        self.wallets.wallets().iter().map(|wallet| wallet.pubkey()).collect()
+    }
+
+    pub fn holdings(&self) -> SharedHoldings {
+        self.holdings.clone()
+    }
+
+    // None without http_rpc
+    pub fn rpc(&self) -> Option<&RpcClient> {
+        self.rpc.as_deref()
+    }
+
+    // fed by the Geyser stream, None unless priority_fee is enabled
+    pub fn fee_oracle(&self) -> Option<SharedPriorityFeeOracle> {
+        self.fee_oracle.clone()
+    }
+
+    // Wallets that cannot pay for their largest buy are left out of the rotation: WSOL is
+    // checked when pre-funded, SOL when buys wrap their own. A split over fewer wallets gives
+    // each a larger share, so the templates are rebuilt and the balances checked again.
+    pub async fn check_wallet_balances(&mut self, rpc: &RpcClient) -> anyhow::Result<()> {
+        loop {
+            let mut funded = HashSet::new();
+            for wallet in self.wallets.wallets() {
+                match wallet_balance(rpc, wallet).await {
+                    Ok(balance) => {
+                        info!("wallet {} balance {} lamports", wallet.pubkey(), balance);
+                        funded.insert(wallet.pubkey());
+                    }
+                    Err(err) => warn!("leaving out wallet {}: {:#}", wallet.pubkey(), err),
+                }
+            }
+
+            self.wallets.retain(|wallet| funded.contains(&wallet.pubkey()));
+            if funded.is_empty() {
+                bail!("no wallet can pay for a buy");
+            }
+            let per_trade = WalletPool::per_trade(&self.wallets_config, funded.len());
+            if per_trade == self.wallets.shares() {
+                return Ok(());
+            }
+
+            warn!("{} funded wallets, buys are now split {} ways", funded.len(), per_trade);
+            let wallets = self
+                .wallets
+                .wallets()
+                .iter()
+                .map(|wallet| build_wallet(wallet.tx_config.clone(), &self.senders, per_trade))
+                .collect();
+            self.wallets = WalletPool::new(wallets, per_trade);
+        }
     }
 
     pub async fn send_and_confirm_transaction(
         tx_index: u32,
         rpc_sender: Arc<dyn TxSender>,
-        recent_blockhash: Hash,
-        accounts_for_buy: AccountsForBuy,
-    ) -> anyhow::Result<()> {
+        transactions: Vec<VersionedTransaction>,
+    ) -> anyhow::Result<TxResult> {
         let start = tokio::time::Instant::now();
 
-        let _tx_result = rpc_sender.send_transaction(tx_index, recent_blockhash, accounts_for_buy).await?;
+        // more than the trade makes a bundle
+        let tx_result = match transactions.as_slice() {
+            [transaction] => rpc_sender.send_transaction(tx_index, transaction).await?,
+            transactions => rpc_sender.send_bundle(transactions).await?,
+        };
 
         info!(
             "complete rpc: {:?} {:?} ms",
             rpc_sender.name(),
             start.elapsed().as_millis() as u64
         );
-        Ok(())
+        Ok(tx_result)
     }
 
-    pub async fn send_buy_tx(self, recent_blockhash: Hash, accounts_for_buy: AccountsForBuy) {
-        tokio::select! {
-            _ = self.send_buy_tx_inner(
-                recent_blockhash,
-                accounts_for_buy,
-            ) => {}
+    // A bundle's outcome comes from its block engine, a landed one is enough. Plain sends are
+    // followed through their signatures, which needs http_rpc: None when nothing can tell.
+    pub async fn landed(&self, sent: &[SentTrade]) -> Option<bool> {
+        let bundles: Vec<_> = sent
+            .iter()
+            .filter_map(|sent_trade| match &sent_trade.result {
+                TxResult::BundleID(bundle_id) => Some((sent_trade, bundle_id)),
+                TxResult::Signature(_) => None,
+            })
+            .collect();
+        let all_bundles = bundles.len() == sent.len() && !sent.is_empty();
+        let bundle_outcomes = join_all(bundles.into_iter().map(|(sent_trade, bundle_id)| async move {
+            let outcome = sent_trade.sender.wait_for_bundle(bundle_id).await;
+            match &outcome {
+                Ok(outcome) => info!("{} bundle {}: {:?}", sent_trade.sender.name(), bundle_id, outcome),
+                Err(err) => warn!(
+                    "{} cannot track bundle {}: {:#}",
+                    sent_trade.sender.name(),
+                    bundle_id,
+                    err
+                ),
+            }
+            outcome
+        }));
+        let (bundle_outcomes, signatures_landed) = tokio::join!(bundle_outcomes, self.signatures_landed(sent));
+
+        if bundle_outcomes.iter().any(|outcome| matches!(outcome, Ok(BundleOutcome::Landed { .. }))) {
+            return Some(true);
+        }
+        if all_bundles && bundle_outcomes.iter().all(|outcome| outcome.is_ok()) {
+            return Some(signatures_landed.unwrap_or(false));
         }
+        signatures_landed
     }
 
-    async fn send_buy_tx_inner(self, recent_blockhash: Hash, accounts_for_buy: AccountsForBuy) {
+    // Whether one of the trade's transactions executed fine, polled until they all failed or
+    // LANDING_TIMEOUT. None without http_rpc to ask.
+    async fn signatures_landed(&self, sent: &[SentTrade]) -> Option<bool> {
+        let rpc = self.rpc.as_ref()?;
+        let signatures: Vec<Signature> = sent.iter().map(|sent_trade| sent_trade.signature).collect();
+        if signatures.is_empty() {
+            return Some(false);
+        }
+
         let start = tokio::time::Instant::now();
-        info!("starting create buy tx");
+        while start.elapsed() < LANDING_TIMEOUT {
+            tokio::time::sleep(LANDING_POLL_INTERVAL).await;
+            let statuses = match rpc.get_signature_statuses(&signatures).await {
+                Ok(response) => response.value,
+                Err(err) => {
+                    warn!("cannot get signature statuses: {:#}", err);
+                    continue;
+                }
+            };
+            let confirmed: Vec<_> = statuses
+                .into_iter()
+                .flatten()
+                .filter(|status| status.satisfies_commitment(CommitmentConfig::confirmed()))
+                .collect();
+            if confirmed.iter().any(|status| status.err.is_none()) {
+                return Some(true);
+            }
+            if confirmed.len() == signatures.len() {
+                return Some(false);
+            }
+        }
+        Some(false)
+    }
+
+    // the position stays open while the buy may land, it is cancelled when it did not
+    pub fn track_buy(&self, policy: SharedSnipePolicy, mint: Pubkey, sent: Vec<SentTrade>) {
+        let bench = self.clone();
+        tokio::spawn(async move {
+            match bench.landed(&sent).await {
+                Some(true) => info!("buy of {} landed", mint),
+                Some(false) => {
+                    let sent: Vec<String> = sent
+                        .into_iter()
+                        .map(|sent_trade| {
+                            format!(
+                                "{} {} from {}",
+                                sent_trade.sender.name(),
+                                String::from(sent_trade.result),
+                                sent_trade.wallet
+                            )
+                        })
+                        .collect();
+                    info!("buy of {} did not land, sent as {:?}", mint, sent);
+                    policy.lock().unwrap().cancel(&mint);
+                }
+                None => {}
+            }
+        });
+    }
+
+    pub async fn send_buy_tx(self, recent_blockhash: Hash, buy_accounts: BuyAccounts) -> Vec<SentTrade> {
+        // a mirrored swap carries its own size, shared between the wallets like buy_amount
+        let shares = self.wallets.shares() as u64;
+        let buy_accounts = match buy_accounts {
+            BuyAccounts::AmmSwap(swap) => BuyAccounts::AmmSwap(AmmSwap {
+                amount_in: swap.amount_in / shares,
+                expected_out: swap.expected_out / shares,
+                ..swap
+            }),
+            buy_accounts => buy_accounts,
+        };
+        let trade = Trade::buy(buy_accounts);
+
+        let sends = self.wallets.next().into_iter().filter_map(|wallet| {
+            let trade = match buy_accounts {
+                // a mirrored sell cannot sell more than the wallet holds
+                BuyAccounts::AmmSwap(swap) if swap.input_mint != WSOL_MINT => {
+                    let held = self.holdings.lock().unwrap().balance(&wallet.pubkey(), &swap.input_mint);
+                    if held == 0 {
+                        info!("wallet {} holds no {}, not selling", wallet.pubkey(), swap.input_mint);
+                        return None;
+                    }
+                    Trade::buy(BuyAccounts::AmmSwap(swap.capped(held)))
+                }
+                _ => trade.clone(),
+            };
+            Some(self.send_tx_inner("buy", wallet, recent_blockhash, trade))
+        });
+        join_all(sends).await.into_iter().flatten().collect()
+    }
+
+    // the sell command trades the private_key wallet only
+    pub async fn send_sell_tx(self, recent_blockhash: Hash, sell_order: SellOrder) {
+        for wallet in self.wallets.next() {
+            let trade = Trade::Prepared(Arc::new(PreparedInstructions::sell(&wallet.tx_config, sell_order)));
+            self.send_tx_inner("sell", wallet, recent_blockhash, trade).await;
+        }
+    }
+
+    // Fans the trade out to every sender. Each task signs its own transaction, so the
+    // senders sign in parallel on the runtime's worker threads.
+    async fn send_tx_inner(
+        &self,
+        label: &'static str,
+        wallet: Arc<Wallet>,
+        recent_blockhash: Hash,
+        trade: Trade,
+    ) -> Vec<SentTrade> {
+        let start = tokio::time::Instant::now();
+        let oracle_fees = self.fee_oracle.as_ref().map(|fee_oracle| fee_oracle.fees());
+        info!(
+            "starting create {} tx from wallet {}, oracle fees {:?}",
+            label,
+            wallet.pubkey(),
+            oracle_fees
+        );
+        let nonce = self.nonce_pool.as_ref().and_then(|nonce_pool| nonce_pool.acquire());
+        if self.nonce_pool.is_some() && nonce.is_none() {
+            warn!(
+                "every nonce account is in use, the {} goes out with the recent blockhash",
+                label
+            );
+        }
         let mut tx_handles = Vec::new();
 
-        for rpc in &self.rpcs {
+        for (rpc, template) in &wallet.rpcs {
             // let rpc_name = rpc.name();
             let rpc_sender = rpc.clone();
+            let template = template.clone();
+            let trade = trade.clone();
             // let client = self.client.clone();
             let hdl = tokio::spawn(async move {
                 let index = 0;
-                if let Err(e) =
-                    Self::send_and_confirm_transaction(index, rpc_sender, recent_blockhash, accounts_for_buy).await
-                {
-                    error!("error end_and_confirm_transaction {:?}", e);
+                let fees = template.tx_config().fees(oracle_fees);
+                let transaction = match template.transaction(recent_blockhash, &trade, fees, nonce) {
+                    Ok(transaction) => transaction,
+                    Err(err) => {
+                        error!("cannot build {} tx for {}: {:#}", label, rpc_sender.name(), err);
+                        return None;
+                    }
+                };
+                info!(
+                    "{} tx {} signed by wallet {} for {}",
+                    label,
+                    transaction.signatures[0],
+                    template.tx_config().keypair.pubkey(),
+                    rpc_sender.name()
+                );
+                let signature = transaction.signatures[0];
+                // a bundle is [trade, tip]
+                let mut transactions = vec![transaction];
+                transactions.extend(template.tip_transaction(recent_blockhash, fees));
+                let sender = rpc_sender.clone();
+                match Self::send_and_confirm_transaction(index, rpc_sender, transactions).await {
+                    Ok(result) => Some(SentTrade {
+                        sender,
+                        wallet: template.tx_config().keypair.pubkey(),
+                        signature,
+                        result,
+                    }),
+                    Err(e) => {
+                        error!("error end_and_confirm_transaction {:?}", e);
+                        None
+                    }
                 }
             });
             tx_handles.push(hdl);
@@ -76,10 +391,156 @@ impl Bench {
         info!("waiting for transactions to complete...");
 
         // wait for all transactions to complete
+        let mut sent = Vec::new();
         for hdl in tx_handles {
-            hdl.await.unwrap_or_default();
+            sent.extend(hdl.await.unwrap_or_default());
+        }
+        if let (Some(nonce_pool), Some(nonce)) = (&self.nonce_pool, nonce) {
+            nonce_pool.release(nonce);
         }
 
         info!("bench complete! {:?} ms", start.elapsed().as_millis() as u64);
+        sent
+    }
+}
+
+// every sender with its own template, tips differ per sender; buy_amount is the wallet's share
+fn build_wallet(
+    tx_config: TransactionConfig,
+    senders: &[(RpcConfig, Arc<dyn TxSender>)],
+    per_trade: usize,
+) -> Arc<Wallet> {
+    let rpcs = senders
+        .iter()
+        .map(|(rpc, sender)| {
+            let mut template_config = tx_config.for_sender(rpc);
+            template_config.buy_amount /= per_trade as u64;
+            let template = Arc::new(TransactionTemplate::new(template_config, rpc.rpc_type.clone()));
+            (sender.clone(), template)
+        })
+        .collect();
+    Arc::new(Wallet { tx_config, rpcs })
+}
+
+// the balance paying for buys, when it covers the largest of the wallet's senders
+async fn wallet_balance(rpc: &RpcClient, wallet: &Wallet) -> anyhow::Result<u64> {
+    let buy_amount = wallet.rpcs.iter().map(|(_, template)| template.tx_config().buy_amount).max().unwrap_or_default();
+    let balance = match wallet.tx_config.wsol_mode {
+        WsolMode::PreFunded => {
+            let wsol_account = get_associated_token_address(&wallet.pubkey(), &WSOL_MINT);
+            rpc.get_token_account_balance(&wsol_account)
+                .await
+                .context(format!("no WSOL account {}", wsol_account))?
+                .amount
+                .parse()?
+        }
+        WsolMode::AutoWrap => rpc.get_balance(&wallet.pubkey()).await?,
+    };
+
+    if balance < buy_amount {
+        bail!(
+            "{:?} balance {} does not cover buy_amount {} lamports",
+            wallet.tx_config.wsol_mode,
+            balance,
+            buy_amount
+        );
+    }
+    Ok(balance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx_senders::tests::mock_rpc;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    // buys of 0.03 SOL split between 3 wallets that wrap their own SOL
+    fn bench(wallets: &[Keypair]) -> Bench {
+        let config: PingThingsArgs = serde_yaml::from_str(&format!(
+            "rpc:
+  local:
+    url: 'http://127.0.0.1:1'
+    rpc_type: solanarpc
+geyser_url: ''
+geyser_x_token: ''
+private_key: {}
+compute_unit_price: 100000
+compute_unit_limit: 100000
+tip: 0.001
+buy_amount: 0.03
+wsol_mode: auto_wrap
+wallets:
+  selection: split
+  split_count: 3",
+            Keypair::new().to_base58_string()
+        ))
+        .unwrap();
+        let wallets = wallets.iter().map(|keypair| keypair.insecure_clone()).collect();
+        Bench::new(config, Vec::new(), None, wallets).unwrap()
+    }
+
+    // getBalance answered from balances, by wallet
+    async fn rpc(balances: HashMap<Pubkey, u64>) -> RpcClient {
+        let url = mock_rpc(move |request, _| {
+            let wallet = Pubkey::from_str(request["params"][0].as_str().unwrap()).unwrap();
+            let balance = balances.get(&wallet).copied().unwrap_or_default();
+            (
+                200,
+                json!({"jsonrpc": "2.0", "id": 0, "result": {"context": {"slot": 1}, "value": balance}}),
+            )
+        })
+        .await;
+        RpcClient::new(url)
+    }
+
+    fn buy_amounts(bench: &Bench) -> Vec<u64> {
+        let wallets = bench.wallets.wallets();
+        wallets
+            .iter()
+            .flat_map(|wallet| wallet.rpcs.iter().map(|(_, template)| template.tx_config().buy_amount))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn split_grows_as_unfunded_wallets_drop_out() {
+        let wallets = [Keypair::new(), Keypair::new(), Keypair::new()];
+        let mut bench = bench(&wallets);
+        assert_eq!(bench.wallets.shares(), 3);
+        assert_eq!(buy_amounts(&bench), [10_000_000; 3]);
+
+        // the second wallet covers a third of the buy but not half of it
+        let rpc = rpc(HashMap::from([
+            (wallets[1].pubkey(), 12_000_000),
+            (wallets[2].pubkey(), 50_000_000),
+        ]))
+        .await;
+        bench.check_wallet_balances(&rpc).await.unwrap();
+
+        assert_eq!(bench.wallet_pubkeys(), HashSet::from([wallets[2].pubkey()]));
+        assert_eq!(bench.wallets.shares(), 1);
+        assert_eq!(buy_amounts(&bench), [30_000_000]);
+        assert_eq!(bench.max_buy_amount(), 30_000_000);
+    }
+
+    #[tokio::test]
+    async fn split_stays_when_every_wallet_is_funded() {
+        let wallets = [Keypair::new(), Keypair::new(), Keypair::new()];
+        let mut bench = bench(&wallets);
+        let rpc = rpc(wallets.iter().map(|wallet| (wallet.pubkey(), 10_000_000)).collect()).await;
+        bench.check_wallet_balances(&rpc).await.unwrap();
+
+        assert_eq!(bench.wallets.shares(), 3);
+        assert_eq!(buy_amounts(&bench), [10_000_000; 3]);
+        assert_eq!(bench.max_buy_amount(), 30_000_000);
+    }
+
+    #[tokio::test]
+    async fn no_funded_wallet_is_an_error() {
+        let wallets = [Keypair::new(), Keypair::new()];
+        let mut bench = bench(&wallets);
+        let rpc = rpc(HashMap::from([(wallets[0].pubkey(), 1_000)])).await;
+        assert!(bench.check_wallet_balances(&rpc).await.is_err());
     }
 }
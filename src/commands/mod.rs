@@ -0,0 +1,157 @@
+use crate::bench::Bench;
+use crate::config::PingThingsArgs;
+use crate::meteora::pool_state::PoolStateCache;
+use crate::tx_senders::lookup_tables::{fetch_lookup_table, load_lookup_tables, static_accounts};
+use crate::tx_senders::nonce::load_nonce_pool;
+use crate::tx_senders::transaction::SellOrder;
+use anyhow::{Context, bail};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::address_lookup_table::instruction::{create_lookup_table, extend_lookup_table};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::nonce::state::State as NonceState;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::system_instruction::create_nonce_account;
+use solana_sdk::transaction::Transaction;
+use spl_associated_token_account::get_associated_token_address;
+use std::str::FromStr;
+use tracing::info;
+
+// addresses per extend transaction, well below the transaction size limit
+const LOOKUP_TABLE_EXTEND_CHUNK: usize = 20;
+
+// One-shot commands, `<binary> <command> [args]`. Without a command the bot snipes.
+pub async fn run(config: PingThingsArgs, command: &str, args: &[String]) -> anyhow::Result<()> {
+    match command {
+        "sell" => sell(config, args).await,
+        "lookup-table" => lookup_table(config, args).await,
+        "nonce-accounts" => nonce_accounts(config, args).await,
+        _ => bail!("unknown command {}", command),
+    }
+}
+
+// sell <pool> [amount]: sells `amount` raw token units, the whole balance by default. The
+// command has no geyser stream, the pool is read once over RPC; the bot quotes from the cache.
+async fn sell(config: PingThingsArgs, args: &[String]) -> anyhow::Result<()> {
+    let pool = args.first().context("usage: sell <pool> [amount]")?;
+    let pool = Pubkey::from_str(pool).context("invalid pool")?;
+    let rpc = config.http_rpc_client().context("http_rpc is not configured")?;
+
+    let accounts_for_buy = PoolStateCache::fetch(&rpc, pool).await?;
+    let owner = Keypair::from_base58_string(&config.private_key).pubkey();
+    let token_account = get_associated_token_address(&owner, &accounts_for_buy.token_mint);
+    let balance: u64 = rpc
+        .get_token_account_balance(&token_account)
+        .await
+        .context(format!("no {} token account", accounts_for_buy.token_mint))?
+        .amount
+        .parse()?;
+
+    let amount_in = match args.get(1) {
+        Some(amount) => amount.parse().context("invalid amount")?,
+        None => balance,
+    };
+    if amount_in == 0 || amount_in > balance {
+        bail!("cannot sell {} tokens, balance is {}", amount_in, balance);
+    }
+
+    let expected_out = accounts_for_buy.quote_sell(amount_in, config.default_trade_fee_bps);
+    let sell_order = SellOrder {
+        swap: accounts_for_buy.sell_swap(amount_in, expected_out),
+        close_token_account: config.sell.close_token_account && amount_in == balance,
+        unwrap_wsol: config.sell.unwrap_wsol,
+    };
+    info!(
+        "selling {} of {} in pool {}, expecting {} lamports",
+        amount_in, accounts_for_buy.token_mint, pool, expected_out
+    );
+
+    let recent_blockhash = rpc.get_latest_blockhash().await?;
+    let lookup_tables = load_lookup_tables(&config).await?;
+    let nonce_pool = load_nonce_pool(&config).await?;
+    let wallets = vec![Keypair::from_base58_string(&config.private_key)];
+    Bench::new(config, lookup_tables, nonce_pool, wallets)?.send_sell_tx(recent_blockhash, sell_order).await;
+    Ok(())
+}
+
+// lookup-table [address]: creates a lookup table, or extends an existing one, with the
+// static accounts the bot's trades reference
+async fn lookup_table(config: PingThingsArgs, args: &[String]) -> anyhow::Result<()> {
+    let rpc = config.http_rpc_client().context("http_rpc is not configured")?;
+    let keypair = Keypair::from_base58_string(&config.private_key);
+    let owner = keypair.pubkey();
+
+    let (lookup_table, existing) = match args.first() {
+        Some(address) => {
+            let address = Pubkey::from_str(address).context("invalid lookup table")?;
+            (address, fetch_lookup_table(&rpc, address).await?.addresses)
+        }
+        None => {
+            // the derivation slot must be recent, finalized keeps it valid until the create lands
+            let recent_slot = rpc.get_slot_with_commitment(CommitmentConfig::finalized()).await?;
+            let (create_instruction, address) = create_lookup_table(owner, owner, recent_slot);
+            let signature = send_instructions(&rpc, &[&keypair], &[create_instruction]).await?;
+            info!("created lookup table {} in {}", address, signature);
+            (address, Vec::new())
+        }
+    };
+
+    let missing: Vec<Pubkey> =
+        static_accounts(&owner).into_iter().filter(|account| !existing.contains(account)).collect();
+    for chunk in missing.chunks(LOOKUP_TABLE_EXTEND_CHUNK) {
+        let extend_instruction = extend_lookup_table(lookup_table, owner, Some(owner), chunk.to_vec());
+        let signature = send_instructions(&rpc, &[&keypair], &[extend_instruction]).await?;
+        info!(
+            "added {} accounts to lookup table {} in {}",
+            chunk.len(),
+            lookup_table,
+            signature
+        );
+    }
+
+    info!("lookup table {} is ready, add it to lookup_tables", lookup_table);
+    Ok(())
+}
+
+// nonce-accounts [count]: creates rent exempt durable nonce accounts, authorized to the bot's
+// wallet, for nonce_accounts
+async fn nonce_accounts(config: PingThingsArgs, args: &[String]) -> anyhow::Result<()> {
+    let count: u32 = match args.first() {
+        Some(count) => count.parse().context("invalid count")?,
+        None => 1,
+    };
+    let rpc = config.http_rpc_client().context("http_rpc is not configured")?;
+    let keypair = Keypair::from_base58_string(&config.private_key);
+    let owner = keypair.pubkey();
+    let lamports = rpc.get_minimum_balance_for_rent_exemption(NonceState::size()).await?;
+
+    let mut created = Vec::new();
+    for _ in 0..count {
+        let nonce_keypair = Keypair::new();
+        let instructions = create_nonce_account(&owner, &nonce_keypair.pubkey(), &owner, lamports);
+        let signature = send_instructions(&rpc, &[&keypair, &nonce_keypair], &instructions).await?;
+        info!(
+            "created nonce account {} with {} lamports in {}",
+            nonce_keypair.pubkey(),
+            lamports,
+            signature
+        );
+        created.push(nonce_keypair.pubkey().to_string());
+    }
+
+    info!("add to nonce_accounts: {:?}", created);
+    Ok(())
+}
+
+// the first signer pays
+async fn send_instructions(
+    rpc: &RpcClient,
+    signers: &[&Keypair],
+    instructions: &[Instruction],
+) -> anyhow::Result<Signature> {
+    let recent_blockhash = rpc.get_latest_blockhash().await?;
+    let transaction =
+        Transaction::new_signed_with_payer(instructions, Some(&signers[0].pubkey()), signers, recent_blockhash);
+    Ok(rpc.send_and_confirm_transaction(&transaction).await?)
+}
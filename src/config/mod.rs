@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
 use std::collections::HashMap;
 use std::fs;
 
@@ -8,12 +9,265 @@ pub struct PingThingsArgs {
     pub rpc: HashMap<String, RpcConfig>,
     pub geyser_url: String,
     pub geyser_x_token: String,
+    // reads for commands, e.g. sell
+    #[serde(default)]
+    pub http_rpc: Option<String>,
+    // the bot's wallet: commands, nonce authority, and trades unless wallets sets a keypair_dir
     pub private_key: String,
+    #[serde(default)]
+    pub wallets: WalletsConfig,
     pub compute_unit_price: u64,
     pub compute_unit_limit: u32,
     pub tip: f64,
+    #[serde(default)]
+    pub tip_accounts: TipAccountsConfig,
+    // replaces compute_unit_price and tip with what competing transactions pay
+    #[serde(default)]
+    pub priority_fee: PriorityFeeConfig,
     pub buy_amount: f64,
+    // static floor, in token units with 6 decimals
+    #[serde(default)]
     pub min_amount_out: f64,
+    #[serde(default = "default_slippage_bps")]
+    pub slippage_bps: u64,
+    // pre_funded when unset, only an explicit pre_funded requires http_rpc
+    #[serde(default)]
+    pub wsol_mode: Option<WsolMode>,
+    // used when the pool's fee is not part of the create instruction
+    #[serde(default = "default_trade_fee_bps")]
+    pub default_trade_fee_bps: u64,
+    // pool programs to snipe
+    #[serde(default = "default_strategies")]
+    pub strategies: Vec<Strategy>,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub filters: FiltersConfig,
+    // mirrors dynamic AMM swaps of these wallets, runs with the damm strategy
+    #[serde(default)]
+    pub copy_trade: CopyTradeConfig,
+    #[serde(default)]
+    pub wait_for_liquidity: WaitForLiquidityConfig,
+    #[serde(default)]
+    pub sell: SellConfig,
+    // address lookup tables for every trade, see the lookup-table command
+    #[serde(default)]
+    pub lookup_tables: Vec<String>,
+    // durable nonce accounts, see the nonce-accounts command; every sender's copy of a trade
+    // signs the same nonce so only one can land
+    #[serde(default)]
+    pub nonce_accounts: Vec<String>,
+}
+
+// Wallets signing the trades, one Solana CLI keypair file (*.json) each in keypair_dir.
+// round_robin sends every trade from the next wallet, split shares every buy between the
+// next split_count wallets.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WalletsConfig {
+    pub keypair_dir: Option<String>,
+    pub selection: WalletSelection,
+    pub split_count: usize,
+}
+
+impl Default for WalletsConfig {
+    fn default() -> Self {
+        WalletsConfig {
+            keypair_dir: None,
+            selection: WalletSelection::RoundRobin,
+            split_count: 2,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletSelection {
+    #[default]
+    RoundRobin,
+    Split,
+}
+
+// Tip accounts per provider, one is picked for every transaction. An empty list keeps
+// the provider's known accounts.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TipAccountsConfig {
+    pub selection: TipAccountSelection,
+    pub jito: Vec<String>,
+    pub bloxroute: Vec<String>,
+    pub nextblock: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TipAccountSelection {
+    #[default]
+    Random,
+    RoundRobin,
+}
+
+// Percentiles of the CU prices and tips paid by Meteora transactions of the last window_slots,
+// clamped to the bounds. compute_unit_price and tip apply until a sample is seen.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PriorityFeeConfig {
+    pub enabled: bool,
+    pub window_slots: u64,
+    pub compute_unit_price_percentile: u8,
+    // micro-lamports per CU
+    pub min_compute_unit_price: u64,
+    pub max_compute_unit_price: u64,
+    pub tip_percentile: u8,
+    // SOL
+    pub min_tip: f64,
+    pub max_tip: f64,
+}
+
+impl Default for PriorityFeeConfig {
+    fn default() -> Self {
+        PriorityFeeConfig {
+            enabled: false,
+            window_slots: 150,
+            compute_unit_price_percentile: 75,
+            min_compute_unit_price: 0,
+            max_compute_unit_price: 50_000_000,
+            tip_percentile: 50,
+            min_tip: 0.0,
+            max_tip: 0.01,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SellConfig {
+    // only when the whole balance is sold
+    pub close_token_account: bool,
+    // closes the WSOL account, buys need it funded again
+    pub unwrap_wsol: bool,
+}
+
+impl Default for SellConfig {
+    fn default() -> Self {
+        SellConfig {
+            close_token_account: true,
+            unwrap_wsol: false,
+        }
+    }
+}
+
+// arm dynamic AMM pools created below min_liquidity_sol and buy on their first large enough deposit
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WaitForLiquidityConfig {
+    pub enabled: bool,
+    pub min_liquidity_sol: f64,
+    pub max_slots: u64,
+}
+
+impl Default for WaitForLiquidityConfig {
+    fn default() -> Self {
+        WaitForLiquidityConfig {
+            enabled: false,
+            min_liquidity_sol: 0.0,
+            // about a minute
+            max_slots: 150,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CopyTradeConfig {
+    pub wallets: Vec<String>,
+    // our size relative to the tracked swap
+    pub size_ratio: f64,
+    // cap on a mirrored buy, in SOL
+    pub max_amount_sol: Option<f64>,
+}
+
+impl Default for CopyTradeConfig {
+    fn default() -> Self {
+        CopyTradeConfig {
+            wallets: Vec::new(),
+            size_ratio: 1.0,
+            max_amount_sol: None,
+        }
+    }
+}
+
+// every filter is off unless configured
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FiltersConfig {
+    pub min_liquidity_sol: Option<f64>,
+    pub max_liquidity_sol: Option<f64>,
+    pub creator_allow_list: Vec<String>,
+    pub creator_block_list: Vec<String>,
+    pub mint_prefix: Option<String>,
+    pub mint_suffix: Option<String>,
+    // prior pools seen from the same creator since startup
+    pub max_creator_pools: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    // None means unlimited
+    pub max_buys: Option<u32>,
+    pub max_open_positions: Option<u32>,
+    pub dedupe_mint: bool,
+    pub dedupe_creator: bool,
+    pub cooldown_ms: u64,
+    pub daily_budget_sol: Option<f64>,
+}
+
+// a single buy per run, like the bot always did
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        PolicyConfig {
+            max_buys: Some(1),
+            max_open_positions: None,
+            dedupe_mint: true,
+            dedupe_creator: false,
+            cooldown_ms: 0,
+            daily_budget_sol: None,
+        }
+    }
+}
+
+// where the WSOL spent by buys comes from
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WsolMode {
+    // a funded WSOL account, its balance is checked at startup
+    #[default]
+    PreFunded,
+    // each buy wraps what it spends and closes the WSOL account afterwards
+    AutoWrap,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    // SPL Token mints only, see the dynamic AMM controller
+    Damm,
+    // SPL Token mints only, the pair is created with a single token program
+    Dlmm,
+    DammV2,
+}
+
+fn default_strategies() -> Vec<Strategy> {
+    vec![Strategy::Damm, Strategy::Dlmm, Strategy::DammV2]
+}
+
+fn default_slippage_bps() -> u64 {
+    1500
+}
+
+fn default_trade_fee_bps() -> u64 {
+    25
 }
 
 #[derive(Debug, Deserialize, Default, Clone)]
@@ -32,6 +286,18 @@ pub struct RpcConfig {
     pub auth: Option<String>,
     #[serde(default)]
     pub rpc_type: RpcType,
+    // jito only: sendBundle with the trade and a separate tip transaction instead of sendTransaction
+    #[serde(default)]
+    pub bundle: bool,
+    // override the global values for this sender only
+    #[serde(default)]
+    pub tip: Option<f64>,
+    #[serde(default)]
+    pub compute_unit_price: Option<u64>,
+    #[serde(default)]
+    pub compute_unit_limit: Option<u32>,
+    #[serde(default)]
+    pub buy_amount: Option<f64>,
 }
 
 impl PingThingsArgs {
@@ -39,4 +305,8 @@ impl PingThingsArgs {
         let config_yaml = fs::read_to_string("./config.yaml").expect("cannot find config file");
         serde_yaml::from_str::<PingThingsArgs>(&config_yaml).expect("invalid config file")
     }
+
+    pub fn http_rpc_client(&self) -> Option<RpcClient> {
+        self.http_rpc.clone().map(RpcClient::new)
+    }
 }
@@ -1,86 +1,181 @@
 use crate::geyser::GeyserResult;
-use solana_sdk::instruction::AccountMeta;
+use solana_sdk::instruction::{AccountMeta, CompiledInstruction, Instruction};
 use solana_sdk::message::VersionedMessage;
 use solana_sdk::message::v0::{LoadedAddresses, LoadedMessage};
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::reserved_account_keys::ReservedAccountKeys;
 use solana_sdk::transaction::VersionedTransaction;
-use solana_transaction_status::TransactionStatusMeta;
+use solana_transaction_status::{TransactionStatusMeta, TransactionTokenBalance};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 
+pub const TOP_LEVEL_STACK_HEIGHT: u32 = 1;
+const PROGRAM_DATA_LOG_PREFIX: &str = "Program data: ";
+
+#[derive(Debug, Clone)]
+pub struct ExtractedInstruction {
+    // index of the top-level instruction, shared by all of its CPIs
+    pub outer_index: usize,
+    // 1 for top-level instructions, > 1 for CPIs
+    pub stack_height: u32,
+    pub instruction: Instruction,
+}
+
+// Top-level instructions in order, each followed by its inner (CPI) instructions.
 pub fn extract_instructions(
-    meta_data: TransactionStatusMeta,
+    meta: TransactionStatusMeta,
     transaction: VersionedTransaction,
-) -> GeyserResult<Vec<solana_sdk::instruction::Instruction>> {
-    let message = transaction.message.clone();
-    let meta = meta_data.clone();
-
-    let mut instructions = Vec::<solana_sdk::instruction::Instruction>::new();
-
-    match message {
-        VersionedMessage::Legacy(legacy) => {
-            for (_i, compiled_instruction) in legacy.instructions.iter().enumerate() {
-                let program_id = *legacy
-                    .account_keys
-                    .get(compiled_instruction.program_id_index as usize)
-                    .unwrap_or(&Pubkey::default());
-
-                let accounts: Vec<_> = compiled_instruction
-                    .accounts
-                    .iter()
-                    .filter_map(|account_index| {
-                        let account_pubkey = legacy.account_keys.get(*account_index as usize)?;
-                        Some(AccountMeta {
-                            pubkey: *account_pubkey,
-                            is_writable: legacy.is_maybe_writable(*account_index as usize, None),
-                            is_signer: legacy.is_signer(*account_index as usize),
-                        })
-                    })
-                    .collect();
-
-                instructions.push(solana_sdk::instruction::Instruction {
-                    program_id,
-                    accounts,
-                    data: compiled_instruction.data.clone(),
-                });
-            }
-        }
+) -> GeyserResult<Vec<ExtractedInstruction>> {
+    let account_metas: Vec<AccountMeta> = match &transaction.message {
+        VersionedMessage::Legacy(legacy) => legacy
+            .account_keys
+            .iter()
+            .enumerate()
+            .map(|(index, pubkey)| AccountMeta {
+                pubkey: *pubkey,
+                is_writable: legacy.is_maybe_writable(index, None),
+                is_signer: legacy.is_signer(index),
+            })
+            .collect(),
         VersionedMessage::V0(v0) => {
             let loaded_addresses = LoadedAddresses {
-                writable: meta.loaded_addresses.writable.iter().map(|key| key.clone()).collect(),
-                readonly: meta.loaded_addresses.readonly.iter().map(|key| key.clone()).collect(),
+                writable: meta.loaded_addresses.writable.to_vec(),
+                readonly: meta.loaded_addresses.readonly.to_vec(),
             };
 
-            let loaded_message =
-                LoadedMessage::new(v0.clone(), loaded_addresses, &ReservedAccountKeys::empty_key_set());
-
-            for (_i, compiled_instruction) in v0.instructions.iter().enumerate() {
-                let program_id = *loaded_message
-                    .account_keys()
-                    .get(compiled_instruction.program_id_index as usize)
-                    .unwrap_or(&Pubkey::default());
-
-                let accounts: Vec<AccountMeta> = compiled_instruction
-                    .accounts
-                    .iter()
-                    .filter_map(|account_index| {
-                        let account_pubkey = loaded_message.account_keys().get(*account_index as usize);
-
-                        return Some(AccountMeta {
-                            pubkey: account_pubkey.map(|acc| acc.clone()).unwrap_or_default(),
-                            is_writable: loaded_message.is_writable(*account_index as usize),
-                            is_signer: loaded_message.is_signer(*account_index as usize),
-                        });
-                    })
-                    .collect();
-
-                instructions.push(solana_sdk::instruction::Instruction {
-                    program_id,
-                    accounts,
-                    data: compiled_instruction.data.clone(),
-                });
-            }
+            let loaded_message = LoadedMessage::new(v0.clone(), loaded_addresses, &HashSet::new());
+
+            loaded_message
+                .account_keys()
+                .iter()
+                .enumerate()
+                .map(|(index, pubkey)| AccountMeta {
+                    pubkey: *pubkey,
+                    is_writable: loaded_message.is_writable(index),
+                    is_signer: loaded_message.is_signer(index),
+                })
+                .collect()
+        }
+    };
+
+    let inner_instructions = meta.inner_instructions.unwrap_or_default();
+    let mut instructions = Vec::<ExtractedInstruction>::new();
+
+    for (outer_index, compiled_instruction) in transaction.message.instructions().iter().enumerate() {
+        instructions.push(ExtractedInstruction {
+            outer_index,
+            stack_height: TOP_LEVEL_STACK_HEIGHT,
+            instruction: resolve_instruction(compiled_instruction, &account_metas),
+        });
+
+        let inner = inner_instructions.iter().filter(|inner| inner.index as usize == outer_index);
+        for inner_instruction in inner.flat_map(|inner| inner.instructions.iter()) {
+            instructions.push(ExtractedInstruction {
+                outer_index,
+                // old nodes do not report the height, all we know is that it is a CPI
+                stack_height: inner_instruction.stack_height.unwrap_or(TOP_LEVEL_STACK_HEIGHT + 1),
+                instruction: resolve_instruction(&inner_instruction.instruction, &account_metas),
+            });
         }
     }
 
     Ok(instructions)
 }
+
+#[derive(Debug, Copy, Clone)]
+pub struct TokenBalanceChange {
+    pub mint: Pubkey,
+    // raw amounts, 0 when the account did not exist before or was closed
+    pub pre: u64,
+    pub post: u64,
+}
+
+// Static keys followed by the writable and readonly lookup table keys, the order
+// token balance indexes refer to.
+pub fn account_keys(meta: &TransactionStatusMeta, transaction: &VersionedTransaction) -> Vec<Pubkey> {
+    let mut keys = transaction.message.static_account_keys().to_vec();
+    keys.extend_from_slice(&meta.loaded_addresses.writable);
+    keys.extend_from_slice(&meta.loaded_addresses.readonly);
+    keys
+}
+
+// token account -> balance before and after the transaction
+pub fn token_balance_changes(
+    meta: &TransactionStatusMeta,
+    account_keys: &[Pubkey],
+) -> HashMap<Pubkey, TokenBalanceChange> {
+    let parse = |balance: &TransactionTokenBalance| -> Option<(Pubkey, Pubkey, u64)> {
+        Some((
+            *account_keys.get(balance.account_index as usize)?,
+            Pubkey::from_str(&balance.mint).ok()?,
+            balance.ui_token_amount.amount.parse().ok()?,
+        ))
+    };
+
+    let mut changes = HashMap::new();
+    for (account, mint, amount) in meta.pre_token_balances.iter().flatten().filter_map(parse) {
+        changes.insert(account, TokenBalanceChange {
+            mint,
+            pre: amount,
+            post: 0,
+        });
+    }
+    for (account, mint, amount) in meta.post_token_balances.iter().flatten().filter_map(parse) {
+        changes.entry(account).or_insert(TokenBalanceChange { mint, pre: 0, post: 0 }).post = amount;
+    }
+
+    changes
+}
+
+// Decoded `Program data:` payloads emitted by `program_id`, in log order. The invoke
+// stack is tracked so data logged by programs it calls (or that call it) is skipped.
+pub fn program_data_logs(logs: &[String], program_id: &Pubkey) -> Vec<Vec<u8>> {
+    let program_id = program_id.to_string();
+    let mut invoke_stack: Vec<&str> = Vec::new();
+    let mut payloads = Vec::new();
+
+    for log in logs {
+        if let Some(payload) = log.strip_prefix(PROGRAM_DATA_LOG_PREFIX) {
+            if invoke_stack.last() == Some(&program_id.as_str()) {
+                if let Ok(payload) = base64::decode(payload) {
+                    payloads.push(payload);
+                }
+            }
+        } else if let Some(rest) = log.strip_prefix("Program ") {
+            let mut words = rest.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some(program), Some("invoke")) => invoke_stack.push(program),
+                (Some(_), Some("success" | "failed:")) => {
+                    invoke_stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    payloads
+}
+
+fn resolve_instruction(compiled_instruction: &CompiledInstruction, account_metas: &[AccountMeta]) -> Instruction {
+    let program_id = account_metas
+        .get(compiled_instruction.program_id_index as usize)
+        .map(|account| account.pubkey)
+        .unwrap_or_default();
+
+    let accounts: Vec<AccountMeta> = compiled_instruction
+        .accounts
+        .iter()
+        .map(|account_index| {
+            account_metas.get(*account_index as usize).cloned().unwrap_or(AccountMeta {
+                pubkey: Pubkey::default(),
+                is_writable: false,
+                is_signer: false,
+            })
+        })
+        .collect();
+
+    Instruction {
+        program_id,
+        accounts,
+        data: compiled_instruction.data.clone(),
+    }
+}
@@ -0,0 +1,361 @@
+use crate::bench::Bench;
+use crate::core::{ExtractedInstruction, account_keys, extract_instructions, token_balance_changes};
+use crate::filters::{PoolCandidate, SharedFilterPipeline};
+use crate::geyser::TransactionHandler;
+use crate::meteora::{BPS_DENOMINATOR, IX_DISCRIMINATOR_SIZE, QuoteSide};
+use crate::policy::SharedSnipePolicy;
+use crate::token_2022::{MintRejection, TransferFee, fetch_mint, inspect_mint};
+use crate::tx_senders::constants::{METEORA_DAMM_V2_PROGRAM, WSOL_MINT};
+use crate::tx_senders::transaction::BuyAccounts;
+use async_trait::async_trait;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::TransactionStatusMeta;
+use tracing::info;
+
+pub const INITIALIZE_POOL_DISC: [u8; 8] = [0x5f, 0xb4, 0x0a, 0xac, 0x54, 0xae, 0xe8, 0x28];
+pub const INITIALIZE_CUSTOMIZABLE_POOL_DISC: [u8; 8] = [0x14, 0xa1, 0xf1, 0x18, 0xbd, 0xdd, 0xb4, 0x02];
+pub const INITIALIZE_POOL_WITH_DYNAMIC_CONFIG_DISC: [u8; 8] = [0x95, 0x52, 0x48, 0xc5, 0xfd, 0xfc, 0x44, 0x0f];
+pub const DAMM_V2_SWAP_IX_DISC: [u8; 8] = [0xf8, 0xc6, 0x9e, 0x91, 0xe1, 0x75, 0x87, 0xc8];
+
+// fee numerators are expressed over this denominator
+pub const FEE_DENOMINATOR: u64 = 1_000_000_000;
+// sqrt prices and liquidity are Q64.64
+pub const Q64: f64 = 18_446_744_073_709_551_616.0;
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct InitializePoolParameters {
+    pub liquidity: u128,
+    pub sqrt_price: u128,
+    pub activation_point: Option<u64>,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct BaseFeeParameters {
+    pub cliff_fee_numerator: u64,
+    pub number_of_period: u16,
+    pub period_frequency: u64,
+    pub reduction_factor: u64,
+    pub fee_scheduler_mode: u8,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct DynamicFeeParameters {
+    pub bin_step: u16,
+    pub bin_step_u128: u128,
+    pub filter_period: u16,
+    pub decay_period: u16,
+    pub reduction_factor: u16,
+    pub max_volatility_accumulator: u32,
+    pub variable_fee_control: u32,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct PoolFeeParameters {
+    pub base_fee: BaseFeeParameters,
+    pub padding: [u8; 3],
+    pub dynamic_fee: Option<DynamicFeeParameters>,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct InitializeCustomizablePoolParameters {
+    pub pool_fees: PoolFeeParameters,
+    pub sqrt_min_price: u128,
+    pub sqrt_max_price: u128,
+    pub has_alpha_vault: bool,
+    pub liquidity: u128,
+    pub sqrt_price: u128,
+    pub activation_type: u8,
+    pub collect_fee_mode: u8,
+    pub activation_point: Option<u64>,
+}
+
+// Accounts needed to buy the non-WSOL token of a DAMM v2 pool, vaults keep the pool's A/B order.
+#[derive(Debug, Copy, Clone)]
+pub struct DammV2AccountsForBuy {
+    pub pool: Pubkey,
+    pub pool_authority: Pubkey,
+    pub token_mint: Pubkey,
+    pub quote_side: QuoteSide,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub token_a_vault: Pubkey,
+    pub token_b_vault: Pubkey,
+    pub token_a_program: Pubkey,
+    pub token_b_program: Pubkey,
+    pub liquidity: u128,
+    pub sqrt_price: u128,
+    // None when the fee lives in the pool config account
+    pub trade_fee_bps: Option<u64>,
+    // Token-2022 transfer fee charged on the bought token
+    pub transfer_fee: Option<TransferFee>,
+}
+
+impl DammV2AccountsForBuy {
+    pub fn quote_vault(&self) -> Pubkey {
+        match self.quote_side {
+            QuoteSide::A => self.token_a_vault,
+            QuoteSide::B => self.token_b_vault,
+        }
+    }
+
+    pub fn token_program(&self) -> Pubkey {
+        match self.quote_side {
+            QuoteSide::A => self.token_b_program,
+            QuoteSide::B => self.token_a_program,
+        }
+    }
+
+    // concentrated-liquidity output inside the current price range, the fee is taken from the input
+    pub fn quote_buy(&self, amount_in: u64, default_trade_fee_bps: u64) -> u64 {
+        let liquidity = self.liquidity as f64 / Q64;
+        let sqrt_price = self.sqrt_price as f64 / Q64;
+        if liquidity == 0.0 || sqrt_price == 0.0 {
+            return 0;
+        }
+
+        let trade_fee_bps = self.trade_fee_bps.unwrap_or(default_trade_fee_bps).min(BPS_DENOMINATOR);
+        let amount_in = amount_in as f64 * (BPS_DENOMINATOR - trade_fee_bps) as f64 / BPS_DENOMINATOR as f64;
+
+        let amount_out = match self.quote_side {
+            // B in, A out: the price moves up
+            QuoteSide::B => {
+                let next_sqrt_price = sqrt_price + amount_in / liquidity;
+                liquidity * (1.0 / sqrt_price - 1.0 / next_sqrt_price)
+            }
+            // A in, B out: the price moves down
+            QuoteSide::A => {
+                let next_sqrt_price = 1.0 / (1.0 / sqrt_price + amount_in / liquidity);
+                liquidity * (sqrt_price - next_sqrt_price)
+            }
+        };
+
+        // the program checks the minimum out against what is left after the transfer fee
+        let amount_out = amount_out as u64;
+        self.transfer_fee.map_or(amount_out, |transfer_fee| transfer_fee.amount_after_fee(amount_out))
+    }
+}
+
+// Account layouts only differ by how many accounts precede the pool authority.
+#[derive(Debug, Copy, Clone)]
+pub struct DammV2PoolInit {
+    pub variant: &'static str,
+    pub creator: Pubkey,
+    pub pool: Pubkey,
+    pub pool_authority: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub token_a_vault: Pubkey,
+    pub token_b_vault: Pubkey,
+    pub token_a_program: Pubkey,
+    pub token_b_program: Pubkey,
+    pub liquidity: u128,
+    pub sqrt_price: u128,
+    pub trade_fee_bps: Option<u64>,
+}
+
+impl DammV2PoolInit {
+    pub fn decode(accounts: &[AccountMeta], data: &[u8]) -> Option<Self> {
+        let discriminator: [u8; 8] = data.get(..IX_DISCRIMINATOR_SIZE)?.try_into().ok()?;
+        let mut args = data.get(IX_DISCRIMINATOR_SIZE..)?;
+
+        let (variant, authority_index) = match discriminator {
+            INITIALIZE_POOL_DISC => ("initialize_pool", 5),
+            INITIALIZE_CUSTOMIZABLE_POOL_DISC => ("initialize_customizable_pool", 4),
+            INITIALIZE_POOL_WITH_DYNAMIC_CONFIG_DISC => ("initialize_pool_with_dynamic_config", 6),
+            _ => return None,
+        };
+
+        let (liquidity, sqrt_price, trade_fee_bps) = if discriminator == INITIALIZE_POOL_DISC {
+            let params = InitializePoolParameters::deserialize(&mut args).ok()?;
+            (params.liquidity, params.sqrt_price, None)
+        } else {
+            let params = InitializeCustomizablePoolParameters::deserialize(&mut args).ok()?;
+            (
+                params.liquidity,
+                params.sqrt_price,
+                Some(cliff_fee_bps(&params.pool_fees)),
+            )
+        };
+
+        // pool authority, pool, position, mints, vaults, payer token accounts, token programs
+        if accounts.len() < authority_index + 11 {
+            return None;
+        }
+        let account = |offset: usize| accounts[authority_index + offset].pubkey;
+
+        Some(DammV2PoolInit {
+            variant,
+            creator: accounts[0].pubkey,
+            pool_authority: account(0),
+            pool: account(1),
+            token_a_mint: account(3),
+            token_b_mint: account(4),
+            token_a_vault: account(5),
+            token_b_vault: account(6),
+            token_a_program: account(9),
+            token_b_program: account(10),
+            liquidity,
+            sqrt_price,
+            trade_fee_bps,
+        })
+    }
+
+    // None when neither side of the pool is WSOL
+    pub fn accounts_for_buy(&self) -> Option<DammV2AccountsForBuy> {
+        let (token_mint, quote_side) = if self.token_b_mint == WSOL_MINT {
+            (self.token_a_mint, QuoteSide::B)
+        } else if self.token_a_mint == WSOL_MINT {
+            (self.token_b_mint, QuoteSide::A)
+        } else {
+            return None;
+        };
+
+        Some(DammV2AccountsForBuy {
+            pool: self.pool,
+            pool_authority: self.pool_authority,
+            token_mint,
+            quote_side,
+            token_a_mint: self.token_a_mint,
+            token_b_mint: self.token_b_mint,
+            token_a_vault: self.token_a_vault,
+            token_b_vault: self.token_b_vault,
+            token_a_program: self.token_a_program,
+            token_b_program: self.token_b_program,
+            liquidity: self.liquidity,
+            sqrt_price: self.sqrt_price,
+            trade_fee_bps: self.trade_fee_bps,
+            transfer_fee: None,
+        })
+    }
+}
+
+// the fee a sniper pays right at activation, before any fee scheduler decay
+fn cliff_fee_bps(pool_fees: &PoolFeeParameters) -> u64 {
+    pool_fees.base_fee.cliff_fee_numerator * BPS_DENOMINATOR / FEE_DENOMINATOR
+}
+
+#[derive(Clone)]
+pub struct DammV2Controller {
+    bench: Bench,
+    policy: SharedSnipePolicy,
+    filters: SharedFilterPipeline,
+}
+
+impl DammV2Controller {
+    pub fn new(bench: Bench, policy: SharedSnipePolicy, filters: SharedFilterPipeline) -> Self {
+        DammV2Controller { bench, policy, filters }
+    }
+
+    // runs the filters and the policy, then sends the buy
+    async fn snipe(
+        &self,
+        pool_init: DammV2PoolInit,
+        accounts_for_buy: DammV2AccountsForBuy,
+        quote_liquidity: Option<u64>,
+        recent_blockhash: Hash,
+    ) {
+        let candidate = PoolCandidate {
+            pool: pool_init.pool,
+            token_mint: accounts_for_buy.token_mint,
+            creator: pool_init.creator,
+            quote_liquidity,
+        };
+        if let Err(rejection) = self.filters.evaluate(&candidate) {
+            info!(
+                "skipping damm v2 pool {}, rejected by {} filter: {}",
+                candidate.pool, rejection.filter, rejection.reason
+            );
+            return;
+        }
+
+        if let Err(rejection) = self.policy.lock().unwrap().try_acquire(accounts_for_buy.token_mint, pool_init.creator)
+        {
+            info!("skipping damm v2 pool {}: {}", pool_init.pool, rejection);
+            return;
+        }
+
+        let sent = self.bench.clone().send_buy_tx(recent_blockhash, BuyAccounts::DammV2(accounts_for_buy)).await;
+        self.bench.track_buy(self.policy.clone(), accounts_for_buy.token_mint, sent);
+    }
+}
+
+#[async_trait]
+impl TransactionHandler for DammV2Controller {
+    async fn transaction_handler(
+        &mut self,
+        _signature: Signature,
+        transaction: VersionedTransaction,
+        meta: TransactionStatusMeta,
+        _is_vote: bool,
+        _slot: u64,
+    ) -> anyhow::Result<()> {
+        // the deposit is derived on-chain from liquidity and the price range, the vault balance tells it
+        let balances = token_balance_changes(&meta, &account_keys(&meta, &transaction));
+        let instructions: Vec<ExtractedInstruction> = extract_instructions(meta, transaction.clone())?;
+        let recent_blockhash: Hash = *transaction.message.recent_blockhash();
+
+        for extracted in &instructions {
+            let instruction = &extracted.instruction;
+            if instruction.program_id != METEORA_DAMM_V2_PROGRAM {
+                continue;
+            }
+
+            let Some(pool_init) = DammV2PoolInit::decode(&instruction.accounts, &instruction.data) else {
+                continue;
+            };
+
+            let Some(mut accounts_for_buy) = pool_init.accounts_for_buy() else {
+                continue;
+            };
+
+            if pool_init.liquidity == 0 {
+                info!("skipping damm v2 pool created without liquidity");
+                continue;
+            }
+
+            info!(
+                "damm v2 pool {} created by {} via {} at instruction {} stack height {}",
+                pool_init.pool, pool_init.creator, pool_init.variant, extracted.outer_index, extracted.stack_height
+            );
+
+            let quote_liquidity = balances.get(&accounts_for_buy.quote_vault()).map(|change| change.post);
+            match inspect_mint(
+                accounts_for_buy.token_mint,
+                accounts_for_buy.token_program(),
+                &instructions,
+            ) {
+                Ok(transfer_fee) => accounts_for_buy.transfer_fee = transfer_fee,
+                // a mint created earlier is fetched off the stream, the buy follows once it is known
+                Err(MintRejection::UnknownExtensions(_)) if self.bench.rpc().is_some() => {
+                    let controller = self.clone();
+                    tokio::spawn(async move {
+                        let Some(rpc) = controller.bench.rpc() else {
+                            return;
+                        };
+                        match fetch_mint(rpc, accounts_for_buy.token_mint).await {
+                            Ok(transfer_fee) => accounts_for_buy.transfer_fee = transfer_fee,
+                            Err(rejection) => {
+                                info!("skipping damm v2 pool {}: {}", pool_init.pool, rejection);
+                                return;
+                            }
+                        }
+                        controller.snipe(pool_init, accounts_for_buy, quote_liquidity, recent_blockhash).await;
+                    });
+                    continue;
+                }
+                Err(rejection) => {
+                    info!("skipping damm v2 pool {}: {}", pool_init.pool, rejection);
+                    continue;
+                }
+            }
+
+            self.snipe(pool_init, accounts_for_buy, quote_liquidity, recent_blockhash).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+use crate::bench::Bench;
+use crate::core::{ExtractedInstruction, account_keys, extract_instructions, token_balance_changes};
+use crate::filters::{PoolCandidate, SharedFilterPipeline};
+use crate::geyser::TransactionHandler;
+use crate::meteora::{BPS_DENOMINATOR, IX_DISCRIMINATOR_SIZE, QuoteSide};
+use crate::policy::SharedSnipePolicy;
+use crate::token_2022::MintRejection;
+use crate::tx_senders::constants::{METEORA_DLMM_PROGRAM, TOKEN_PROGRAM, WSOL_MINT};
+use crate::tx_senders::transaction::BuyAccounts;
+use async_trait::async_trait;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::TransactionStatusMeta;
+use tracing::info;
+
+pub const INITIALIZE_LB_PAIR_DISC: [u8; 8] = [0x2d, 0x9a, 0xed, 0xd2, 0xdd, 0x0f, 0xa6, 0x5c];
+pub const INITIALIZE_CUSTOMIZABLE_PERMISSIONLESS_LB_PAIR_DISC: [u8; 8] =
+    [0x2e, 0x27, 0x29, 0x87, 0x6f, 0xb7, 0xc8, 0x40];
+pub const DLMM_SWAP_IX_DISC: [u8; 8] = [0xf8, 0xc6, 0x9e, 0x91, 0xe1, 0x75, 0x87, 0xc8];
+
+pub const MAX_BIN_PER_ARRAY: i32 = 70;
+// base fee rates are expressed over this precision
+pub const FEE_PRECISION: u64 = 1_000_000_000;
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct InitializeLbPairIxData {
+    pub active_id: i32,
+    pub bin_step: u16,
+}
+
+// leading fields of the customizable pair params, the trailing padding is ignored
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct CustomizableLbPairParams {
+    pub active_id: i32,
+    pub bin_step: u16,
+    pub base_factor: u16,
+    pub activation_type: u8,
+    pub has_alpha_vault: bool,
+    pub activation_point: Option<u64>,
+    pub creator_pool_on_off_control: bool,
+    pub base_fee_power_factor: u8,
+}
+
+// Accounts needed to buy the non-WSOL token of an LB pair. `quote_side` A is token X, B is token Y.
+#[derive(Debug, Copy, Clone)]
+pub struct DlmmAccountsForBuy {
+    pub lb_pair: Pubkey,
+    pub token_mint: Pubkey,
+    pub quote_side: QuoteSide,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reserve_x: Pubkey,
+    pub reserve_y: Pubkey,
+    pub oracle: Pubkey,
+    // owns both mints, the pair's reserves are created with it
+    pub token_program: Pubkey,
+    pub bin_array_bitmap_extension: Option<Pubkey>,
+    // active bin array first, then its neighbour in the swap direction
+    pub bin_arrays: [Pubkey; 2],
+    pub active_id: i32,
+    pub bin_step: u16,
+    // None when the fee lives in the preset parameter account
+    pub trade_fee_bps: Option<u64>,
+}
+
+impl DlmmAccountsForBuy {
+    pub fn quote_reserve(&self) -> Pubkey {
+        match self.quote_side {
+            QuoteSide::A => self.reserve_x,
+            QuoteSide::B => self.reserve_y,
+        }
+    }
+
+    // the reserve a buy is paid out from
+    pub fn token_reserve(&self) -> Pubkey {
+        match self.quote_side {
+            QuoteSide::A => self.reserve_y,
+            QuoteSide::B => self.reserve_x,
+        }
+    }
+
+    // output at the active bin price, price is token Y per token X in raw units
+    pub fn quote_buy(&self, amount_in: u64, default_trade_fee_bps: u64) -> u64 {
+        let trade_fee_bps = self.trade_fee_bps.unwrap_or(default_trade_fee_bps).min(BPS_DENOMINATOR);
+        let amount_in = amount_in as f64 * (BPS_DENOMINATOR - trade_fee_bps) as f64 / BPS_DENOMINATOR as f64;
+        let price = bin_price(self.active_id, self.bin_step);
+        match self.quote_side {
+            QuoteSide::A => (amount_in * price) as u64,
+            QuoteSide::B => (amount_in / price) as u64,
+        }
+    }
+}
+
+pub fn bin_price(active_id: i32, bin_step: u16) -> f64 {
+    (1.0 + bin_step as f64 / BPS_DENOMINATOR as f64).powi(active_id)
+}
+
+pub fn bin_array_index(bin_id: i32) -> i64 {
+    bin_id.div_euclid(MAX_BIN_PER_ARRAY) as i64
+}
+
+pub fn derive_bin_array(lb_pair: &Pubkey, index: i64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"bin_array", lb_pair.as_ref(), &index.to_le_bytes()],
+        &METEORA_DLMM_PROGRAM,
+    )
+    .0
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LbPairInit {
+    pub lb_pair: Pubkey,
+    pub bin_array_bitmap_extension: Option<Pubkey>,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reserve_x: Pubkey,
+    pub reserve_y: Pubkey,
+    pub oracle: Pubkey,
+    pub funder: Pubkey,
+    pub token_program: Pubkey,
+    pub active_id: i32,
+    pub bin_step: u16,
+    pub trade_fee_bps: Option<u64>,
+}
+
+impl LbPairInit {
+    // both initialize variants share the account layout up to the token program
+    pub fn decode(accounts: &[AccountMeta], data: &[u8]) -> Option<Self> {
+        if accounts.len() < 10 {
+            return None;
+        }
+        let discriminator = data.get(..IX_DISCRIMINATOR_SIZE)?;
+        let mut args = data.get(IX_DISCRIMINATOR_SIZE..)?;
+
+        let (active_id, bin_step, trade_fee_bps) = if discriminator == INITIALIZE_LB_PAIR_DISC {
+            let InitializeLbPairIxData { active_id, bin_step } = InitializeLbPairIxData::deserialize(&mut args).ok()?;
+            (active_id, bin_step, None)
+        } else if discriminator == INITIALIZE_CUSTOMIZABLE_PERMISSIONLESS_LB_PAIR_DISC {
+            let params = CustomizableLbPairParams::deserialize(&mut args).ok()?;
+            let base_fee_rate = params.base_factor as u64
+                * params.bin_step as u64
+                * 10
+                * 10u64.checked_pow(params.base_fee_power_factor as u32)?;
+            (
+                params.active_id,
+                params.bin_step,
+                Some(base_fee_rate * BPS_DENOMINATOR / FEE_PRECISION),
+            )
+        } else {
+            return None;
+        };
+
+        let bin_array_bitmap_extension = Some(accounts[1].pubkey).filter(|pubkey| *pubkey != METEORA_DLMM_PROGRAM);
+
+        Some(LbPairInit {
+            lb_pair: accounts[0].pubkey,
+            bin_array_bitmap_extension,
+            token_x_mint: accounts[2].pubkey,
+            token_y_mint: accounts[3].pubkey,
+            reserve_x: accounts[4].pubkey,
+            reserve_y: accounts[5].pubkey,
+            oracle: accounts[6].pubkey,
+            funder: accounts[8].pubkey,
+            token_program: accounts[9].pubkey,
+            active_id,
+            bin_step,
+            trade_fee_bps,
+        })
+    }
+
+    // None when neither side of the pair is WSOL
+    pub fn accounts_for_buy(&self) -> Option<DlmmAccountsForBuy> {
+        // buying X with Y pushes the active bin up, buying Y with X pushes it down
+        let (token_mint, quote_side, direction) = if self.token_y_mint == WSOL_MINT {
+            (self.token_x_mint, QuoteSide::B, 1)
+        } else if self.token_x_mint == WSOL_MINT {
+            (self.token_y_mint, QuoteSide::A, -1)
+        } else {
+            return None;
+        };
+
+        let index = bin_array_index(self.active_id);
+        let bin_arrays = [
+            derive_bin_array(&self.lb_pair, index),
+            derive_bin_array(&self.lb_pair, index + direction),
+        ];
+
+        Some(DlmmAccountsForBuy {
+            lb_pair: self.lb_pair,
+            token_mint,
+            quote_side,
+            token_x_mint: self.token_x_mint,
+            token_y_mint: self.token_y_mint,
+            reserve_x: self.reserve_x,
+            reserve_y: self.reserve_y,
+            oracle: self.oracle,
+            token_program: self.token_program,
+            bin_array_bitmap_extension: self.bin_array_bitmap_extension,
+            bin_arrays,
+            active_id: self.active_id,
+            bin_step: self.bin_step,
+            trade_fee_bps: self.trade_fee_bps,
+        })
+    }
+}
+
+pub struct DlmmController {
+    bench: Bench,
+    policy: SharedSnipePolicy,
+    filters: SharedFilterPipeline,
+}
+
+impl DlmmController {
+    pub fn new(bench: Bench, policy: SharedSnipePolicy, filters: SharedFilterPipeline) -> Self {
+        DlmmController { bench, policy, filters }
+    }
+}
+
+#[async_trait]
+impl TransactionHandler for DlmmController {
+    async fn transaction_handler(
+        &mut self,
+        _signature: Signature,
+        transaction: VersionedTransaction,
+        meta: TransactionStatusMeta,
+        _is_vote: bool,
+        _slot: u64,
+    ) -> anyhow::Result<()> {
+        // liquidity comes with a later instruction, the reserve balance tells whether this transaction added any
+        let balances = token_balance_changes(&meta, &account_keys(&meta, &transaction));
+        let instructions: Vec<ExtractedInstruction> = extract_instructions(meta, transaction.clone())?;
+
+        for extracted in instructions {
+            let instruction = &extracted.instruction;
+            if instruction.program_id != METEORA_DLMM_PROGRAM {
+                continue;
+            }
+
+            let Some(lb_pair_init) = LbPairInit::decode(&instruction.accounts, &instruction.data) else {
+                continue;
+            };
+
+            let Some(accounts_for_buy) = lb_pair_init.accounts_for_buy() else {
+                continue;
+            };
+
+            // the swap carries no transfer hook accounts and its quote ignores transfer fees
+            if lb_pair_init.token_program != TOKEN_PROGRAM {
+                let rejection =
+                    MintRejection::UnsupportedProgram(accounts_for_buy.token_mint, lb_pair_init.token_program);
+                info!("skipping lb pair {}: {}", lb_pair_init.lb_pair, rejection);
+                continue;
+            }
+
+            // the pair is created empty, a buy before the first deposit has nothing to swap against
+            if balances.get(&accounts_for_buy.token_reserve()).is_none_or(|change| change.post == 0) {
+                info!("skipping lb pair {} created without liquidity", lb_pair_init.lb_pair);
+                continue;
+            }
+
+            info!(
+                "lb pair {} created by {} active id {} bin step {} at instruction {} stack height {}",
+                lb_pair_init.lb_pair,
+                lb_pair_init.funder,
+                lb_pair_init.active_id,
+                lb_pair_init.bin_step,
+                extracted.outer_index,
+                extracted.stack_height
+            );
+
+            let candidate = PoolCandidate {
+                pool: lb_pair_init.lb_pair,
+                token_mint: accounts_for_buy.token_mint,
+                creator: lb_pair_init.funder,
+                quote_liquidity: balances.get(&accounts_for_buy.quote_reserve()).map(|change| change.post),
+            };
+            if let Err(rejection) = self.filters.evaluate(&candidate) {
+                info!(
+                    "skipping lb pair {}, rejected by {} filter: {}",
+                    candidate.pool, rejection.filter, rejection.reason
+                );
+                continue;
+            }
+
+            if let Err(rejection) =
+                self.policy.lock().unwrap().try_acquire(accounts_for_buy.token_mint, lb_pair_init.funder)
+            {
+                info!("skipping lb pair {}: {}", lb_pair_init.lb_pair, rejection);
+                continue;
+            }
+
+            let recent_blockhash: Hash = *transaction.message.recent_blockhash();
+            let sent = self.bench.clone().send_buy_tx(recent_blockhash, BuyAccounts::Dlmm(accounts_for_buy)).await;
+            self.bench.track_buy(self.policy.clone(), accounts_for_buy.token_mint, sent);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+use crate::config::FiltersConfig;
+use anyhow::Context;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+pub type SharedFilterPipeline = Arc<FilterPipeline>;
+
+// What a controller knows about a freshly created pool.
+#[derive(Debug, Copy, Clone)]
+pub struct PoolCandidate {
+    pub pool: Pubkey,
+    pub token_mint: Pubkey,
+    pub creator: Pubkey,
+    // WSOL deposited at creation in lamports, None when unknown
+    pub quote_liquidity: Option<u64>,
+}
+
+pub trait PoolFilter: Send + Sync {
+    fn name(&self) -> &'static str;
+    // Err carries the rejection reason
+    fn check(&self, candidate: &PoolCandidate) -> Result<(), String>;
+}
+
+pub struct FilterRejection {
+    pub filter: &'static str,
+    pub reason: String,
+}
+
+#[derive(Default)]
+pub struct FilterPipeline {
+    filters: Vec<Box<dyn PoolFilter>>,
+}
+
+impl FilterPipeline {
+    pub fn new(config: &FiltersConfig) -> anyhow::Result<Self> {
+        let mut pipeline = FilterPipeline::default();
+
+        // first, so every observed pool is counted even when a later filter rejects it
+        if let Some(max_pools) = config.max_creator_pools {
+            pipeline.push(CreatorHistoryFilter::new(max_pools));
+        }
+
+        if config.min_liquidity_sol.is_some() || config.max_liquidity_sol.is_some() {
+            pipeline.push(LiquidityFilter {
+                min: config.min_liquidity_sol.map(sol_to_lamports),
+                max: config.max_liquidity_sol.map(sol_to_lamports),
+            });
+        }
+
+        if !config.creator_allow_list.is_empty() || !config.creator_block_list.is_empty() {
+            pipeline.push(CreatorListFilter {
+                allow: parse_pubkeys(&config.creator_allow_list)?,
+                block: parse_pubkeys(&config.creator_block_list)?,
+            });
+        }
+
+        if config.mint_prefix.is_some() || config.mint_suffix.is_some() {
+            pipeline.push(MintPatternFilter {
+                prefix: config.mint_prefix.clone(),
+                suffix: config.mint_suffix.clone(),
+            });
+        }
+
+        Ok(pipeline)
+    }
+
+    pub fn push(&mut self, filter: impl PoolFilter + 'static) {
+        self.filters.push(Box::new(filter));
+    }
+
+    pub fn evaluate(&self, candidate: &PoolCandidate) -> Result<(), FilterRejection> {
+        for filter in &self.filters {
+            filter.check(candidate).map_err(|reason| FilterRejection {
+                filter: filter.name(),
+                reason,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+pub struct LiquidityFilter {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+impl PoolFilter for LiquidityFilter {
+    fn name(&self) -> &'static str {
+        "liquidity"
+    }
+
+    // a pool of unknown liquidity passes, the quote and slippage still bound the buy
+    fn check(&self, candidate: &PoolCandidate) -> Result<(), String> {
+        let Some(liquidity) = candidate.quote_liquidity else {
+            return Ok(());
+        };
+
+        if let Some(min) = self.min.filter(|min| liquidity < *min) {
+            return Err(format!("{} lamports below minimum {}", liquidity, min));
+        }
+        if let Some(max) = self.max.filter(|max| liquidity > *max) {
+            return Err(format!("{} lamports above maximum {}", liquidity, max));
+        }
+        Ok(())
+    }
+}
+
+pub struct CreatorListFilter {
+    // empty allows every creator
+    pub allow: HashSet<Pubkey>,
+    pub block: HashSet<Pubkey>,
+}
+
+impl PoolFilter for CreatorListFilter {
+    fn name(&self) -> &'static str {
+        "creator_list"
+    }
+
+    fn check(&self, candidate: &PoolCandidate) -> Result<(), String> {
+        if self.block.contains(&candidate.creator) {
+            return Err(format!("creator {} is blocked", candidate.creator));
+        }
+        if !self.allow.is_empty() && !self.allow.contains(&candidate.creator) {
+            return Err(format!("creator {} is not allowed", candidate.creator));
+        }
+        Ok(())
+    }
+}
+
+pub struct MintPatternFilter {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+}
+
+impl PoolFilter for MintPatternFilter {
+    fn name(&self) -> &'static str {
+        "mint_pattern"
+    }
+
+    fn check(&self, candidate: &PoolCandidate) -> Result<(), String> {
+        let mint = candidate.token_mint.to_string();
+        if let Some(prefix) = self.prefix.as_ref().filter(|prefix| !mint.starts_with(prefix.as_str())) {
+            return Err(format!("mint {} does not start with {}", mint, prefix));
+        }
+        if let Some(suffix) = self.suffix.as_ref().filter(|suffix| !mint.ends_with(suffix.as_str())) {
+            return Err(format!("mint {} does not end with {}", mint, suffix));
+        }
+        Ok(())
+    }
+}
+
+// Counts pools per creator seen since startup, serial creators are usually ruggers. Pools
+// created before the bot started are not looked up, a restart forgets every creator.
+pub struct CreatorHistoryFilter {
+    max_pools: u32,
+    pools_by_creator: Mutex<HashMap<Pubkey, u32>>,
+}
+
+impl CreatorHistoryFilter {
+    pub fn new(max_pools: u32) -> Self {
+        CreatorHistoryFilter {
+            max_pools,
+            pools_by_creator: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl PoolFilter for CreatorHistoryFilter {
+    fn name(&self) -> &'static str {
+        "creator_history"
+    }
+
+    fn check(&self, candidate: &PoolCandidate) -> Result<(), String> {
+        let mut pools_by_creator = self.pools_by_creator.lock().unwrap();
+        let prior_pools = pools_by_creator.entry(candidate.creator).or_insert(0);
+        let count = *prior_pools;
+        *prior_pools += 1;
+
+        if count > self.max_pools {
+            return Err(format!("creator {} has {} prior pools", candidate.creator, count));
+        }
+        Ok(())
+    }
+}
+
+fn sol_to_lamports(sol: f64) -> u64 {
+    (sol * LAMPORTS_PER_SOL as f64) as u64
+}
+
+fn parse_pubkeys(keys: &[String]) -> anyhow::Result<HashSet<Pubkey>> {
+    keys.iter().map(|key| Pubkey::from_str(key).context(format!("invalid pubkey {} in filters config", key))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(quote_liquidity: Option<u64>) -> PoolCandidate {
+        PoolCandidate {
+            pool: Pubkey::new_unique(),
+            token_mint: Pubkey::new_unique(),
+            creator: Pubkey::new_unique(),
+            quote_liquidity,
+        }
+    }
+
+    #[test]
+    fn liquidity_bounds() {
+        let filter = LiquidityFilter {
+            min: Some(1_000),
+            max: Some(5_000),
+        };
+        assert!(filter.check(&candidate(Some(999))).is_err());
+        assert!(filter.check(&candidate(Some(1_000))).is_ok());
+        assert!(filter.check(&candidate(Some(5_000))).is_ok());
+        assert!(filter.check(&candidate(Some(5_001))).is_err());
+        assert!(filter.check(&candidate(None)).is_ok());
+    }
+
+    #[test]
+    fn creator_lists() {
+        let allowed = Pubkey::new_unique();
+        let blocked = Pubkey::new_unique();
+        let filter = CreatorListFilter {
+            allow: HashSet::from([allowed, blocked]),
+            block: HashSet::from([blocked]),
+        };
+        let with_creator = |creator| PoolCandidate {
+            creator,
+            ..candidate(None)
+        };
+        assert!(filter.check(&with_creator(allowed)).is_ok());
+        // blocking wins over allowing
+        assert!(filter.check(&with_creator(blocked)).is_err());
+        assert!(filter.check(&with_creator(Pubkey::new_unique())).is_err());
+
+        let block_only = CreatorListFilter {
+            allow: HashSet::new(),
+            block: HashSet::from([blocked]),
+        };
+        assert!(block_only.check(&with_creator(Pubkey::new_unique())).is_ok());
+    }
+
+    #[test]
+    fn mint_pattern() {
+        let with_mint = PoolCandidate {
+            token_mint: Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap(),
+            ..candidate(None)
+        };
+        let filter = |prefix: Option<&str>, suffix: Option<&str>| MintPatternFilter {
+            prefix: prefix.map(str::to_string),
+            suffix: suffix.map(str::to_string),
+        };
+        assert!(filter(Some("So1"), Some("112")).check(&with_mint).is_ok());
+        assert!(filter(None, None).check(&with_mint).is_ok());
+        assert!(filter(Some("so1"), None).check(&with_mint).is_err());
+        assert!(filter(None, Some("So1")).check(&with_mint).is_err());
+    }
+
+    #[test]
+    fn creator_history_counts_every_pool() {
+        let filter = CreatorHistoryFilter::new(1);
+        let creator = Pubkey::new_unique();
+        let from_creator = || PoolCandidate {
+            creator,
+            ..candidate(None)
+        };
+        assert!(filter.check(&from_creator()).is_ok());
+        assert!(filter.check(&from_creator()).is_ok());
+        assert!(filter.check(&from_creator()).is_err());
+        assert!(filter.check(&candidate(None)).is_ok());
+    }
+
+    #[test]
+    fn pipeline_reports_the_rejecting_filter() {
+        let config = FiltersConfig {
+            min_liquidity_sol: Some(1.0),
+            max_creator_pools: Some(0),
+            ..FiltersConfig::default()
+        };
+        let pipeline = FilterPipeline::new(&config).unwrap();
+        assert!(pipeline.evaluate(&candidate(Some(LAMPORTS_PER_SOL))).is_ok());
+        let rejection = pipeline.evaluate(&candidate(Some(LAMPORTS_PER_SOL / 2))).err().unwrap();
+        assert_eq!(rejection.filter, "liquidity");
+        assert!(FilterPipeline::default().evaluate(&candidate(Some(0))).is_ok());
+    }
+
+    #[test]
+    fn invalid_list_entry_is_an_error() {
+        let config = FiltersConfig {
+            creator_block_list: vec!["not a pubkey".to_string()],
+            ..FiltersConfig::default()
+        };
+        assert!(FilterPipeline::new(&config).is_err());
+    }
+}
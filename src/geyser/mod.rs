@@ -1,13 +1,14 @@
-use crate::meteora::MeteoraController;
 use async_trait::async_trait;
-use futures::StreamExt;
+use futures::{SinkExt, StreamExt};
+use solana_sdk::transaction::VersionedTransaction;
 use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::TransactionStatusMeta;
 use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::time::Duration;
 use std::{collections::HashMap, sync::Arc};
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, watch};
 use tracing::error;
 use yellowstone_grpc_client::GeyserGrpcClient;
 use yellowstone_grpc_proto::tonic::transport::ClientTlsConfig;
@@ -15,7 +16,7 @@ use yellowstone_grpc_proto::{
     convert_from::{create_tx_meta, create_tx_versioned},
     geyser::{
         CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeRequestFilterTransactions,
-        subscribe_update::UpdateOneof,
+        SubscribeUpdateTransaction, subscribe_update::UpdateOneof,
     },
 };
 
@@ -27,6 +28,8 @@ pub struct YellowstoneGrpcGeyserClient {
     pub account_filters: HashMap<String, SubscribeRequestFilterAccounts>,
     pub transaction_filters: HashMap<String, SubscribeRequestFilterTransactions>,
     pub account_deletions_tracked: Arc<RwLock<HashSet<Pubkey>>>,
+    // accounts that come and go at runtime, subscribed under ACCOUNT_SUBSCRIPTIONS_FILTER
+    pub account_subscriptions: Option<watch::Receiver<Vec<String>>>,
 }
 
 impl YellowstoneGrpcGeyserClient {
@@ -37,6 +40,7 @@ impl YellowstoneGrpcGeyserClient {
         account_filters: HashMap<String, SubscribeRequestFilterAccounts>,
         transaction_filters: HashMap<String, SubscribeRequestFilterTransactions>,
         account_deletions_tracked: Arc<RwLock<HashSet<Pubkey>>>,
+        account_subscriptions: Option<watch::Receiver<Vec<String>>>,
     ) -> Self {
         YellowstoneGrpcGeyserClient {
             endpoint,
@@ -45,15 +49,39 @@ impl YellowstoneGrpcGeyserClient {
             account_filters,
             transaction_filters,
             account_deletions_tracked,
+            account_subscriptions,
         }
     }
 }
 
 pub type GeyserResult<T> = Result<T, Error>;
 
+const ACCOUNT_SUBSCRIPTIONS_FILTER: &str = "account_subscriptions";
+
+#[async_trait]
+pub trait TransactionHandler: Send {
+    async fn transaction_handler(
+        &mut self,
+        signature: Signature,
+        transaction: VersionedTransaction,
+        meta: TransactionStatusMeta,
+        is_vote: bool,
+        slot: u64,
+    ) -> anyhow::Result<()>;
+}
+
+// Account updates are applied synchronously, handlers only cache state.
+pub trait AccountHandler: Send + Sync {
+    fn account_handler(&self, pubkey: Pubkey, data: &[u8], slot: u64);
+}
+
 #[async_trait]
 pub trait YellowstoneGrpcGeyser: Send + Sync {
-    async fn consume(&self, meteora_controller: MeteoraController) -> GeyserResult<()>;
+    async fn consume(
+        &self,
+        handlers: Vec<Box<dyn TransactionHandler>>,
+        account_handlers: Vec<Arc<dyn AccountHandler>>,
+    ) -> GeyserResult<()>;
 }
 
 #[derive(Error, Debug)]
@@ -62,15 +90,94 @@ pub enum Error {
     Custom(String),
 }
 
+// An empty account filter matches every account, so the filter is dropped instead.
+fn set_account_subscriptions(subscribe_request: &mut SubscribeRequest, accounts: Vec<String>) {
+    if accounts.is_empty() {
+        subscribe_request.accounts.remove(ACCOUNT_SUBSCRIPTIONS_FILTER);
+        return;
+    }
+    subscribe_request.accounts.insert(
+        ACCOUNT_SUBSCRIPTIONS_FILTER.to_string(),
+        SubscribeRequestFilterAccounts {
+            account: accounts,
+            owner: vec![],
+            filters: vec![],
+            nonempty_txn_signature: None,
+        },
+    );
+}
+
+// Next change of the subscribed accounts, never resolves without a receiver.
+async fn next_account_subscriptions(receiver: &mut Option<watch::Receiver<Vec<String>>>) -> Vec<String> {
+    if let Some(active) = receiver {
+        if active.changed().await.is_ok() {
+            return active.borrow_and_update().clone();
+        }
+    }
+    *receiver = None;
+    std::future::pending().await
+}
+
+async fn handle_transaction(
+    handlers: &mut [Box<dyn TransactionHandler>],
+    transaction_update: SubscribeUpdateTransaction,
+) {
+    let _start_time = std::time::Instant::now();
+
+    let Some(transaction_info) = transaction_update.transaction else {
+        error!(
+            "No transaction info in `UpdateOneof::Transaction` at slot {}",
+            transaction_update.slot
+        );
+        return;
+    };
+    let Ok(signature) = Signature::try_from(transaction_info.signature) else {
+        return;
+    };
+    let Some(yellowstone_transaction) = transaction_info.transaction else {
+        return;
+    };
+    let Some(yellowstone_tx_meta) = transaction_info.meta else {
+        return;
+    };
+    let Ok(versioned_transaction) = create_tx_versioned(yellowstone_transaction) else {
+        return;
+    };
+    let meta_original = match create_tx_meta(yellowstone_tx_meta) {
+        Ok(meta) => meta,
+        Err(err) => {
+            error!("Failed to create transaction meta: {:?}", err);
+            return;
+        }
+    };
+    // info!("signature {:?}", signature);
+    for handler in handlers.iter_mut() {
+        let _ = handler
+            .transaction_handler(
+                signature,
+                versioned_transaction.clone(),
+                meta_original.clone(),
+                transaction_info.is_vote,
+                transaction_update.slot,
+            )
+            .await;
+    }
+}
+
 #[async_trait]
 impl YellowstoneGrpcGeyser for YellowstoneGrpcGeyserClient {
-    async fn consume(&self, mut meteora_controller: MeteoraController) -> GeyserResult<()> {
+    async fn consume(
+        &self,
+        mut handlers: Vec<Box<dyn TransactionHandler>>,
+        account_handlers: Vec<Arc<dyn AccountHandler>>,
+    ) -> GeyserResult<()> {
         let endpoint = self.endpoint.clone();
         let x_token = self.x_token.clone();
         let commitment = self.commitment;
         let account_filters = self.account_filters.clone();
         let transaction_filters = self.transaction_filters.clone();
         let _account_deletions_tracked = self.account_deletions_tracked.clone();
+        let mut account_subscriptions = self.account_subscriptions.clone();
 
         let mut geyser_client = GeyserGrpcClient::build_from_shared(endpoint)
             .map_err(|err| Error::Custom(err.to_string()))?
@@ -85,7 +192,7 @@ impl YellowstoneGrpcGeyser for YellowstoneGrpcGeyserClient {
             .map_err(|err| Error::Custom(err.to_string()))?;
 
         let _ = tokio::spawn(async move {
-            let subscribe_request = SubscribeRequest {
+            let mut subscribe_request = SubscribeRequest {
                 slots: HashMap::new(),
                 accounts: account_filters,
                 transactions: transaction_filters,
@@ -100,63 +207,52 @@ impl YellowstoneGrpcGeyser for YellowstoneGrpcGeyserClient {
             };
 
             loop {
+                // a resubscription starts from the accounts watched right now
+                if let Some(receiver) = account_subscriptions.as_mut() {
+                    let accounts = receiver.borrow_and_update().clone();
+                    set_account_subscriptions(&mut subscribe_request, accounts);
+                }
+
                 match geyser_client.subscribe_with_request(Some(subscribe_request.clone())).await {
-                    Ok((_subscribe_tx, mut stream)) => {
-                        while let Some(message) = stream.next().await {
-                            match message {
-                                Ok(msg) => match msg.update_oneof {
-                                    Some(UpdateOneof::Transaction(transaction_update)) => {
-                                        let _start_time = std::time::Instant::now();
-
-                                        if let Some(transaction_info) = transaction_update.transaction {
-                                            let Ok(signature) = Signature::try_from(transaction_info.signature) else {
-                                                continue;
-                                            };
-                                            let Some(yellowstone_transaction) = transaction_info.transaction else {
-                                                continue;
-                                            };
-                                            let Some(yellowstone_tx_meta) = transaction_info.meta else {
+                    Ok((mut subscribe_tx, mut stream)) => loop {
+                        tokio::select! {
+                            message = stream.next() => {
+                                let Some(message) = message else {
+                                    break;
+                                };
+                                match message {
+                                    Ok(msg) => match msg.update_oneof {
+                                        Some(UpdateOneof::Transaction(transaction_update)) => {
+                                            handle_transaction(&mut handlers, transaction_update).await;
+                                        }
+                                        Some(UpdateOneof::Account(account_update)) => {
+                                            let Some(account_info) = account_update.account else {
                                                 continue;
                                             };
-                                            let Ok(versioned_transaction) =
-                                                create_tx_versioned(yellowstone_transaction)
-                                            else {
+                                            let Ok(pubkey) = Pubkey::try_from(account_info.pubkey.as_slice()) else {
                                                 continue;
                                             };
-                                            let meta_original = match create_tx_meta(yellowstone_tx_meta) {
-                                                Ok(meta) => meta,
-                                                Err(err) => {
-                                                    error!("Failed to create transaction meta: {:?}", err);
-                                                    continue;
-                                                }
-                                            };
-                                            // info!("signature {:?}", signature);
-                                            let _ = meteora_controller
-                                                .transaction_handler(
-                                                    signature,
-                                                    versioned_transaction,
-                                                    meta_original,
-                                                    transaction_info.is_vote,
-                                                    transaction_update.slot,
-                                                )
-                                                .await;
-                                        } else {
-                                            error!(
-                                                "No transaction info in `UpdateOneof::Transaction` at slot {}",
-                                                transaction_update.slot
-                                            );
+                                            for handler in &account_handlers {
+                                                handler.account_handler(pubkey, &account_info.data, account_update.slot);
+                                            }
                                         }
+                                        _ => {}
+                                    },
+                                    Err(error) => {
+                                        error!("Geyser stream error: {error:?}");
+                                        break;
                                     }
-
-                                    _ => {}
-                                },
-                                Err(error) => {
-                                    error!("Geyser stream error: {error:?}");
+                                }
+                            }
+                            accounts = next_account_subscriptions(&mut account_subscriptions) => {
+                                set_account_subscriptions(&mut subscribe_request, accounts);
+                                if let Err(error) = subscribe_tx.send(subscribe_request.clone()).await {
+                                    error!("Failed to update account subscriptions: {error:?}");
                                     break;
                                 }
                             }
                         }
-                    }
+                    },
                     Err(e) => {
                         error!("Failed to subscribe: {:?}", e);
                     }
@@ -1,21 +1,40 @@
 use crate::bench::Bench;
-use crate::config::PingThingsArgs;
-use crate::geyser::{GeyserResult, YellowstoneGrpcGeyser, YellowstoneGrpcGeyserClient};
+use crate::config::{PingThingsArgs, Strategy, WsolMode};
+use crate::geyser::{
+    AccountHandler, Error, GeyserResult, TransactionHandler, YellowstoneGrpcGeyser, YellowstoneGrpcGeyserClient,
+};
+use damm_v2::DammV2Controller;
+use dlmm::DlmmController;
+use filters::FilterPipeline;
 use meteora::MeteoraController;
+use meteora::copy_trade::CopyTrader;
+use meteora::pool_state::PoolStateCache;
+use meteora::wait_for_liquidity::LiquidityWatcher;
+use policy::{PositionTracker, SnipePolicy};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::info;
-use tx_senders::constants::METEORA_POOLS_PROGRAM;
+use tracing::{info, warn};
+use tx_senders::constants::{METEORA_DAMM_V2_PROGRAM, METEORA_DLMM_PROGRAM, METEORA_POOLS_PROGRAM};
+use tx_senders::lookup_tables::load_lookup_tables;
+use tx_senders::nonce::load_nonce_pool;
+use tx_senders::wallets::load_wallets;
 use yellowstone_grpc_proto::geyser::{
     CommitmentLevel, SubscribeRequestFilterAccounts, SubscribeRequestFilterTransactions,
 };
 
 mod bench;
+mod commands;
 mod config;
 mod core;
+mod damm_v2;
+mod dlmm;
+mod filters;
 mod geyser;
 mod meteora;
+mod policy;
+mod priority_fee;
+mod token_2022;
 mod tx_senders;
 
 #[tokio::main]
@@ -28,21 +47,109 @@ pub async fn main() -> GeyserResult<()> {
     .unwrap();
 
     let config_controller: PingThingsArgs = PingThingsArgs::new();
-    let bench_controller: Bench = Bench::new(config_controller.clone());
 
-    let meteora_controller = MeteoraController::new(bench_controller.clone());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        return commands::run(config_controller, command, &args[1..])
+            .await
+            .map_err(|err| Error::Custom(format!("{err:#}")));
+    }
+
+    let lookup_tables =
+        load_lookup_tables(&config_controller).await.map_err(|err| Error::Custom(format!("{err:#}")))?;
+    let nonce_pool = load_nonce_pool(&config_controller).await.map_err(|err| Error::Custom(format!("{err:#}")))?;
+    let wallets = load_wallets(&config_controller).map_err(|err| Error::Custom(format!("{err:#}")))?;
+    let mut bench_controller: Bench = Bench::new(config_controller.clone(), lookup_tables, nonce_pool, wallets)
+        .map_err(|err| Error::Custom(format!("{err:#}")))?;
+
+    match config_controller.http_rpc_client() {
+        Some(rpc) => {
+            bench_controller.check_wallet_balances(&rpc).await.map_err(|err| Error::Custom(format!("{err:#}")))?
+        }
+        // a pre-funded WSOL account that is missing or empty fails every buy
+        None if config_controller.wsol_mode == Some(WsolMode::PreFunded) => {
+            return Err(Error::Custom(
+                "http_rpc is required to check the WSOL accounts of wsol_mode pre_funded".to_string(),
+            ));
+        }
+        None => warn!("http_rpc is not configured, skipping the wallet balance checks"),
+    }
+
+    let policy = SnipePolicy::shared(config_controller.policy.clone(), bench_controller.max_buy_amount());
+    let filters =
+        Arc::new(FilterPipeline::new(&config_controller.filters).map_err(|err| Error::Custom(format!("{err:#}")))?);
+
+    let mut handlers: Vec<Box<dyn TransactionHandler>> = Vec::new();
+    let mut account_handlers: Vec<Arc<dyn AccountHandler>> = Vec::new();
+    let mut account_subscriptions = None;
+    // pool programs and copy-traded wallets
+    let mut account_include: Vec<String> = Vec::new();
+
+    // our own trades, positions are released once sold and mirrored sells capped at what we hold
+    let wallets = bench_controller.wallet_pubkeys();
+    account_include.extend(wallets.iter().map(|wallet| wallet.to_string()));
+    handlers.push(Box::new(PositionTracker::new(
+        policy.clone(),
+        bench_controller.holdings(),
+        wallets,
+    )));
+
+    // samples the same Meteora transactions as the controllers
+    if let Some(fee_oracle) = bench_controller.fee_oracle() {
+        handlers.push(Box::new(fee_oracle));
+    }
+
+    for strategy in &config_controller.strategies {
+        match strategy {
+            Strategy::Damm => {
+                let copy_trader = CopyTrader::new(&config_controller.copy_trade);
+                if copy_trader.is_some() {
+                    account_include.extend(config_controller.copy_trade.wallets.iter().cloned());
+                }
+                let pool_state = PoolStateCache::shared();
+                account_subscriptions = Some(pool_state.subscriptions());
+                account_handlers.push(pool_state.clone());
+                handlers.push(Box::new(MeteoraController::new(
+                    bench_controller.clone(),
+                    policy.clone(),
+                    filters.clone(),
+                    copy_trader,
+                    LiquidityWatcher::new(&config_controller.wait_for_liquidity),
+                    pool_state,
+                )));
+                account_include.push(METEORA_POOLS_PROGRAM.to_string());
+            }
+            Strategy::Dlmm => {
+                handlers.push(Box::new(DlmmController::new(
+                    bench_controller.clone(),
+                    policy.clone(),
+                    filters.clone(),
+                )));
+                account_include.push(METEORA_DLMM_PROGRAM.to_string());
+            }
+            Strategy::DammV2 => {
+                handlers.push(Box::new(DammV2Controller::new(
+                    bench_controller.clone(),
+                    policy.clone(),
+                    filters.clone(),
+                )));
+                account_include.push(METEORA_DAMM_V2_PROGRAM.to_string());
+            }
+        }
+    }
 
     info!("starting with config {:?}", config_controller);
 
     env_logger::init();
     dotenv::dotenv().ok();
 
+    // pool state accounts are added at runtime through account_subscriptions
     let account_filters: HashMap<String, SubscribeRequestFilterAccounts> = HashMap::new();
 
     let transaction_filter = SubscribeRequestFilterTransactions {
         vote: Some(false),
         failed: Some(false),
-        account_include: vec![METEORA_POOLS_PROGRAM.to_string()],
+        account_include,
         account_exclude: vec![],
         account_required: vec![],
         signature: None,
@@ -58,8 +165,9 @@ pub async fn main() -> GeyserResult<()> {
         account_filters,
         transaction_filters,
         Arc::new(RwLock::new(HashSet::new())),
+        account_subscriptions,
     );
 
-    let _ = yellowstone_grpc.consume(meteora_controller).await;
+    let _ = yellowstone_grpc.consume(handlers, account_handlers).await;
     Ok(())
 }
@@ -0,0 +1,92 @@
+use crate::config::CopyTradeConfig;
+use crate::core::TokenBalanceChange;
+use crate::meteora::AmmSwap;
+use crate::meteora::events::SwapEvent;
+use crate::meteora::instructions::{SwapAccounts, SwapArgs};
+use crate::tx_senders::constants::WSOL_MINT;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+pub struct CopyTrader {
+    wallets: HashSet<Pubkey>,
+    size_ratio: f64,
+    // lamports
+    max_amount: Option<u64>,
+}
+
+impl CopyTrader {
+    // None when no wallet is tracked
+    pub fn new(config: &CopyTradeConfig) -> Option<Self> {
+        if config.wallets.is_empty() {
+            return None;
+        }
+
+        let wallets =
+            config.wallets.iter().map(|wallet| Pubkey::from_str(wallet).expect("invalid copy trade wallet")).collect();
+
+        Some(CopyTrader {
+            wallets,
+            size_ratio: config.size_ratio,
+            max_amount: config.max_amount_sol.map(|sol| (sol * LAMPORTS_PER_SOL as f64) as u64),
+        })
+    }
+
+    pub fn tracks(&self, wallet: &Pubkey) -> bool {
+        self.wallets.contains(wallet)
+    }
+
+    // Our side of a tracked swap: same pool and direction, scaled size, quoted at the
+    // price the tracked wallet got. Err explains why the swap cannot be mirrored.
+    pub fn mirror(
+        &self,
+        accounts: &SwapAccounts,
+        args: &SwapArgs,
+        swap_event: Option<&SwapEvent>,
+        balances: &HashMap<Pubkey, TokenBalanceChange>,
+    ) -> Result<AmmSwap, String> {
+        let source = balances
+            .get(&accounts.user_source_token)
+            .ok_or(format!("no balance for source account {}", accounts.user_source_token))?;
+        let destination = balances.get(&accounts.user_destination_token).ok_or(format!(
+            "no balance for destination account {}",
+            accounts.user_destination_token
+        ))?;
+
+        // the event is exact, the balance delta also counts anything else the transaction did to the account
+        let amount_out = swap_event
+            .map(|swap_event| swap_event.out_amount)
+            .unwrap_or(destination.post.saturating_sub(destination.pre));
+        if args.in_amount == 0 || amount_out == 0 {
+            return Err("tracked swap moved no tokens".to_string());
+        }
+
+        let mut amount_in = (args.in_amount as f64 * self.size_ratio) as u64;
+        if source.mint == WSOL_MINT {
+            amount_in = self.max_amount.map_or(amount_in, |max_amount| amount_in.min(max_amount));
+        }
+        if amount_in == 0 {
+            return Err("scaled amount is zero".to_string());
+        }
+
+        let expected_out = (amount_out as u128 * amount_in as u128 / args.in_amount as u128) as u64;
+
+        Ok(AmmSwap {
+            pool: accounts.pool,
+            input_mint: source.mint,
+            output_mint: destination.mint,
+            a_vault: accounts.a_vault,
+            b_vault: accounts.b_vault,
+            a_token_vault: accounts.a_token_vault,
+            b_token_vault: accounts.b_token_vault,
+            a_vault_lp_mint: accounts.a_vault_lp_mint,
+            b_vault_lp_mint: accounts.b_vault_lp_mint,
+            a_vault_lp: accounts.a_vault_lp,
+            b_vault_lp: accounts.b_vault_lp,
+            protocol_token_fee: accounts.protocol_token_fee,
+            amount_in,
+            expected_out,
+        })
+    }
+}
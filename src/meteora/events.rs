@@ -0,0 +1,121 @@
+// Events follow the program IDL field for field, only the ones strategies read are decoded.
+
+use crate::core::program_data_logs;
+use crate::meteora::IX_DISCRIMINATOR_SIZE;
+use crate::tx_senders::constants::METEORA_POOLS_PROGRAM;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+// sha256("event:<Name>")[..8]
+pub const ADD_LIQUIDITY_EVENT_DISC: [u8; 8] = [0x1f, 0x5e, 0x7d, 0x5a, 0xe3, 0x34, 0x3d, 0xba];
+pub const SWAP_EVENT_DISC: [u8; 8] = [0x51, 0x6c, 0xe3, 0xbe, 0xcd, 0xd0, 0x0a, 0xc4];
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone, Copy)]
+pub struct SwapEvent {
+    pub in_amount: u64,
+    pub out_amount: u64,
+    pub trade_fee: u64,
+    pub protocol_fee: u64,
+    pub host_fee: u64,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone, Copy)]
+pub struct AddLiquidityEvent {
+    pub lp_mint_amount: u64,
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MeteoraEvent {
+    Swap(SwapEvent),
+    AddLiquidity(AddLiquidityEvent),
+}
+
+impl MeteoraEvent {
+    // None for events the bot does not use and malformed payloads
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let discriminator: [u8; 8] = data.get(..IX_DISCRIMINATOR_SIZE)?.try_into().ok()?;
+        let mut payload = &data[IX_DISCRIMINATOR_SIZE..];
+
+        let event = match discriminator {
+            SWAP_EVENT_DISC => MeteoraEvent::Swap(BorshDeserialize::deserialize(&mut payload).ok()?),
+            ADD_LIQUIDITY_EVENT_DISC => MeteoraEvent::AddLiquidity(BorshDeserialize::deserialize(&mut payload).ok()?),
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+// Pool program events of a transaction in emission order, so the n-th swap event
+// belongs to the n-th successful swap instruction.
+pub fn parse_events(logs: &[String]) -> Vec<MeteoraEvent> {
+    program_data_logs(logs, &METEORA_POOLS_PROGRAM).iter().filter_map(|data| MeteoraEvent::decode(data)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logs(data: &[&str]) -> Vec<String> {
+        let program = METEORA_POOLS_PROGRAM.to_string();
+        let mut logs = vec![format!("Program {} invoke [1]", program)];
+        logs.extend(data.iter().map(|data| format!("Program data: {}", data)));
+        logs.push(format!("Program {} success", program));
+        logs
+    }
+
+    #[test]
+    fn swap_event_log() {
+        // swap of 1 SOL for 2.5 tokens, 0.25% trade fee of which a fifth goes to the protocol
+        let events = parse_events(&logs(&[
+            "UWzjvs3QCsQAypo7AAAAAKAlJgAAAAAAoCUmAAAAAAAgoQcAAAAAAAAAAAAAAAAA",
+        ]));
+        let [MeteoraEvent::Swap(swap_event)] = events.as_slice() else {
+            panic!("expected one swap event, got {:?}", events);
+        };
+        assert_eq!(swap_event.in_amount, 1_000_000_000);
+        assert_eq!(swap_event.out_amount, 2_500_000);
+        assert_eq!(swap_event.trade_fee, 2_500_000);
+        assert_eq!(swap_event.protocol_fee, 500_000);
+        assert_eq!(swap_event.host_fee, 0);
+    }
+
+    #[test]
+    fn add_liquidity_event_log() {
+        let events = parse_events(&logs(&["H159WuM0PbqGewAAAAAAAEBCDwAAAAAAAMqaOwAAAAA="]));
+        let [MeteoraEvent::AddLiquidity(add_liquidity_event)] = events.as_slice() else {
+            panic!("expected one add liquidity event, got {:?}", events);
+        };
+        assert_eq!(add_liquidity_event.lp_mint_amount, 31_622);
+        assert_eq!(add_liquidity_event.token_a_amount, 1_000_000);
+        assert_eq!(add_liquidity_event.token_b_amount, 1_000_000_000);
+    }
+
+    #[test]
+    fn wrong_discriminator_is_skipped() {
+        let mut data = SWAP_EVENT_DISC.to_vec();
+        data[0] ^= 0xff;
+        data.extend_from_slice(&[0u8; 40]);
+        assert!(MeteoraEvent::decode(&data).is_none());
+    }
+
+    #[test]
+    fn truncated_payload_is_skipped() {
+        let mut data = SWAP_EVENT_DISC.to_vec();
+        data.extend_from_slice(&[0u8; 39]);
+        assert!(MeteoraEvent::decode(&data).is_none());
+    }
+
+    #[test]
+    fn events_of_other_programs_are_skipped() {
+        let mut logs = logs(&[]);
+        let other = solana_sdk::pubkey::Pubkey::new_unique();
+        logs.insert(1, format!("Program {} invoke [2]", other));
+        logs.insert(
+            2,
+            "Program data: UWzjvs3QCsQAypo7AAAAAKAlJgAAAAAAoCUmAAAAAAAgoQcAAAAAAAAAAAAAAAAA".to_string(),
+        );
+        logs.insert(3, format!("Program {} success", other));
+        assert!(parse_events(&logs).is_empty());
+    }
+}
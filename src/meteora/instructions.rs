@@ -0,0 +1,341 @@
+// Typed view of the dynamic AMM instructions the bot acts on, mirroring the program IDL.
+
+use crate::meteora::IX_DISCRIMINATOR_SIZE;
+use crate::meteora::pool_init::{PoolInit, find_pool_init_variant};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+pub const SWAP_DISC: [u8; 8] = [0xf8, 0xc6, 0x9e, 0x91, 0xe1, 0x75, 0x87, 0xc8];
+pub const ADD_BALANCE_LIQUIDITY_DISC: [u8; 8] = [0xa8, 0xe3, 0x32, 0x3e, 0xbd, 0xab, 0x54, 0xb0];
+pub const ADD_IMBALANCE_LIQUIDITY_DISC: [u8; 8] = [0x4f, 0x23, 0x7a, 0x54, 0xad, 0x0f, 0x5d, 0xbf];
+
+pub const INITIALIZE_PERMISSIONED_POOL_DISC: [u8; 8] = [0x4d, 0x55, 0xb2, 0x9d, 0x32, 0x30, 0xd4, 0x7e];
+pub const REMOVE_BALANCE_LIQUIDITY_DISC: [u8; 8] = [0x85, 0x6d, 0x2c, 0xb3, 0x38, 0xee, 0x72, 0x21];
+pub const REMOVE_LIQUIDITY_SINGLE_SIDE_DISC: [u8; 8] = [0x54, 0x54, 0xb1, 0x42, 0xfe, 0xb9, 0x0a, 0xfb];
+pub const BOOTSTRAP_LIQUIDITY_DISC: [u8; 8] = [0x04, 0xe4, 0xd7, 0x47, 0xe1, 0xfd, 0x77, 0xce];
+
+pub const ENABLE_OR_DISABLE_POOL_DISC: [u8; 8] = [0x80, 0x06, 0xe4, 0x83, 0x37, 0xa1, 0x34, 0xa9];
+pub const SET_POOL_FEES_DISC: [u8; 8] = [0x66, 0x2c, 0x9e, 0x36, 0xcd, 0x25, 0x7e, 0x4e];
+pub const OVERRIDE_CURVE_PARAM_DISC: [u8; 8] = [0x62, 0x56, 0xcc, 0x33, 0x5e, 0x47, 0x45, 0xbb];
+pub const GET_POOL_INFO_DISC: [u8; 8] = [0x09, 0x30, 0xdc, 0x65, 0x16, 0xf0, 0x4e, 0xc8];
+pub const CREATE_MINT_METADATA_DISC: [u8; 8] = [0x0d, 0x46, 0xa8, 0x29, 0xfa, 0x64, 0x94, 0x5a];
+pub const CREATE_LOCK_ESCROW_DISC: [u8; 8] = [0x36, 0x57, 0xa5, 0x13, 0x45, 0xe3, 0xda, 0xe0];
+pub const LOCK_DISC: [u8; 8] = [0x15, 0x13, 0xd0, 0x2b, 0xed, 0x3e, 0xff, 0x57];
+pub const CLAIM_FEE_DISC: [u8; 8] = [0xa9, 0x20, 0x4f, 0x89, 0x88, 0xe8, 0x46, 0x89];
+pub const CREATE_CONFIG_DISC: [u8; 8] = [0xc9, 0xcf, 0xf3, 0x72, 0x4b, 0x6f, 0x2f, 0xbd];
+pub const CLOSE_CONFIG_DISC: [u8; 8] = [0x91, 0x09, 0x48, 0x9d, 0x5f, 0x7d, 0x3d, 0x55];
+pub const UPDATE_ACTIVATION_POINT_DISC: [u8; 8] = [0x96, 0x3e, 0x7d, 0xdb, 0xab, 0xdc, 0x1a, 0xed];
+pub const WITHDRAW_PROTOCOL_FEES_DISC: [u8; 8] = [0x0b, 0x44, 0xa5, 0x62, 0x12, 0xd0, 0x86, 0x49];
+pub const SET_WHITELISTED_VAULT_DISC: [u8; 8] = [0x0c, 0x94, 0x5e, 0x2a, 0x37, 0x39, 0x53, 0xf7];
+pub const PARTNER_CLAIM_FEE_DISC: [u8; 8] = [0x39, 0x35, 0xb0, 0x1e, 0x7b, 0x46, 0x34, 0x40];
+
+// instructions the bot never acts on, recognized by name only
+pub static OTHER_INSTRUCTIONS: [(&str, [u8; 8]); 18] = [
+    ("initialize_permissioned_pool", INITIALIZE_PERMISSIONED_POOL_DISC),
+    ("remove_balance_liquidity", REMOVE_BALANCE_LIQUIDITY_DISC),
+    ("remove_liquidity_single_side", REMOVE_LIQUIDITY_SINGLE_SIDE_DISC),
+    ("bootstrap_liquidity", BOOTSTRAP_LIQUIDITY_DISC),
+    ("enable_or_disable_pool", ENABLE_OR_DISABLE_POOL_DISC),
+    ("set_pool_fees", SET_POOL_FEES_DISC),
+    ("override_curve_param", OVERRIDE_CURVE_PARAM_DISC),
+    ("get_pool_info", GET_POOL_INFO_DISC),
+    ("create_mint_metadata", CREATE_MINT_METADATA_DISC),
+    ("create_lock_escrow", CREATE_LOCK_ESCROW_DISC),
+    ("lock", LOCK_DISC),
+    ("claim_fee", CLAIM_FEE_DISC),
+    ("create_config", CREATE_CONFIG_DISC),
+    ("close_config", CLOSE_CONFIG_DISC),
+    ("update_activation_point", UPDATE_ACTIVATION_POINT_DISC),
+    ("withdraw_protocol_fees", WITHDRAW_PROTOCOL_FEES_DISC),
+    ("set_whitelisted_vault", SET_WHITELISTED_VAULT_DISC),
+    ("partner_claim_fee", PARTNER_CLAIM_FEE_DISC),
+];
+
+#[derive(Error, Debug)]
+pub enum DecodeError {
+    #[error("instruction data is {0} bytes, too short for a discriminator")]
+    MissingDiscriminator(usize),
+    #[error("unknown discriminator {0:02x?}")]
+    UnknownDiscriminator([u8; 8]),
+    #[error("{instruction} needs {expected} accounts, got {actual}")]
+    MissingAccounts {
+        instruction: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("invalid {instruction} args: {source}")]
+    InvalidArgs {
+        instruction: &'static str,
+        source: std::io::Error,
+    },
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct SwapArgs {
+    pub in_amount: u64,
+    pub minimum_out_amount: u64,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct AddImbalanceLiquidityArgs {
+    pub minimum_pool_token_amount: u64,
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SwapAccounts {
+    pub pool: Pubkey,
+    pub user_source_token: Pubkey,
+    pub user_destination_token: Pubkey,
+    pub a_vault: Pubkey,
+    pub b_vault: Pubkey,
+    pub a_token_vault: Pubkey,
+    pub b_token_vault: Pubkey,
+    pub a_vault_lp_mint: Pubkey,
+    pub b_vault_lp_mint: Pubkey,
+    pub a_vault_lp: Pubkey,
+    pub b_vault_lp: Pubkey,
+    pub protocol_token_fee: Pubkey,
+    pub user: Pubkey,
+}
+
+impl SwapAccounts {
+    // up to the token program
+    const LEN: usize = 15;
+
+    fn decode(accounts: &[AccountMeta]) -> Self {
+        SwapAccounts {
+            pool: accounts[0].pubkey,
+            user_source_token: accounts[1].pubkey,
+            user_destination_token: accounts[2].pubkey,
+            a_vault: accounts[3].pubkey,
+            b_vault: accounts[4].pubkey,
+            a_token_vault: accounts[5].pubkey,
+            b_token_vault: accounts[6].pubkey,
+            a_vault_lp_mint: accounts[7].pubkey,
+            b_vault_lp_mint: accounts[8].pubkey,
+            a_vault_lp: accounts[9].pubkey,
+            b_vault_lp: accounts[10].pubkey,
+            protocol_token_fee: accounts[11].pubkey,
+            user: accounts[12].pubkey,
+        }
+    }
+}
+
+// shared by add_balance and add_imbalance liquidity, only the pool is needed to match a deposit
+#[derive(Debug, Copy, Clone)]
+pub struct LiquidityAccounts {
+    pub pool: Pubkey,
+}
+
+impl LiquidityAccounts {
+    const LEN: usize = 16;
+
+    fn decode(accounts: &[AccountMeta]) -> Self {
+        LiquidityAccounts {
+            pool: accounts[0].pubkey,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MeteoraInstruction {
+    PoolInit(PoolInit),
+    Swap {
+        accounts: SwapAccounts,
+        args: SwapArgs,
+    },
+    // the args only carry maximums, the deposit comes from the event
+    AddBalanceLiquidity {
+        accounts: LiquidityAccounts,
+    },
+    AddImbalanceLiquidity {
+        accounts: LiquidityAccounts,
+        args: AddImbalanceLiquidityArgs,
+    },
+    Other(&'static str),
+}
+
+// Never panics: short data, missing accounts and malformed args all come back as a DecodeError.
+pub fn decode_instruction(accounts: &[AccountMeta], data: &[u8]) -> Result<MeteoraInstruction, DecodeError> {
+    let discriminator: [u8; 8] = data
+        .get(..IX_DISCRIMINATOR_SIZE)
+        .and_then(|discriminator| discriminator.try_into().ok())
+        .ok_or(DecodeError::MissingDiscriminator(data.len()))?;
+    let args = &data[IX_DISCRIMINATOR_SIZE..];
+
+    if let Some(variant) = find_pool_init_variant(data) {
+        return variant.decode(accounts, data).map(MeteoraInstruction::PoolInit);
+    }
+
+    let instruction = match discriminator {
+        SWAP_DISC => {
+            let name = "swap";
+            let accounts = check_accounts(name, accounts, SwapAccounts::LEN)?;
+            MeteoraInstruction::Swap {
+                accounts: SwapAccounts::decode(accounts),
+                args: decode_args(name, args)?,
+            }
+        }
+        ADD_BALANCE_LIQUIDITY_DISC => {
+            let name = "add_balance_liquidity";
+            let accounts = check_accounts(name, accounts, LiquidityAccounts::LEN)?;
+            MeteoraInstruction::AddBalanceLiquidity {
+                accounts: LiquidityAccounts::decode(accounts),
+            }
+        }
+        ADD_IMBALANCE_LIQUIDITY_DISC => {
+            let name = "add_imbalance_liquidity";
+            let accounts = check_accounts(name, accounts, LiquidityAccounts::LEN)?;
+            MeteoraInstruction::AddImbalanceLiquidity {
+                accounts: LiquidityAccounts::decode(accounts),
+                args: decode_args(name, args)?,
+            }
+        }
+        _ => {
+            let (name, _) = OTHER_INSTRUCTIONS
+                .iter()
+                .find(|(_, other)| *other == discriminator)
+                .ok_or(DecodeError::UnknownDiscriminator(discriminator))?;
+            MeteoraInstruction::Other(name)
+        }
+    };
+    Ok(instruction)
+}
+
+pub fn check_accounts<'a>(
+    instruction: &'static str,
+    accounts: &'a [AccountMeta],
+    expected: usize,
+) -> Result<&'a [AccountMeta], DecodeError> {
+    if accounts.len() < expected {
+        return Err(DecodeError::MissingAccounts {
+            instruction,
+            expected,
+            actual: accounts.len(),
+        });
+    }
+    Ok(accounts)
+}
+
+// trailing bytes are allowed, newer program versions append optional args
+pub fn decode_args<T: BorshDeserialize>(instruction: &'static str, mut data: &[u8]) -> Result<T, DecodeError> {
+    T::deserialize(&mut data).map_err(|source| DecodeError::InvalidArgs { instruction, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meteora::pool_init::{POOL_INIT_VARIANTS, PoolInitLayout};
+
+    fn accounts(count: usize) -> Vec<AccountMeta> {
+        (0..count).map(|_| AccountMeta::new(Pubkey::new_unique(), false)).collect()
+    }
+
+    fn data(discriminator: [u8; 8], args: &[u8]) -> Vec<u8> {
+        [discriminator.as_slice(), args].concat()
+    }
+
+    // every decoded instruction with the accounts it needs
+    fn decoded() -> Vec<(&'static str, [u8; 8], usize)> {
+        let mut decoded = vec![
+            ("swap", SWAP_DISC, SwapAccounts::LEN),
+            (
+                "add_balance_liquidity",
+                ADD_BALANCE_LIQUIDITY_DISC,
+                LiquidityAccounts::LEN,
+            ),
+            (
+                "add_imbalance_liquidity",
+                ADD_IMBALANCE_LIQUIDITY_DISC,
+                LiquidityAccounts::LEN,
+            ),
+        ];
+        for variant in &POOL_INIT_VARIANTS {
+            let expected = match variant.layout {
+                PoolInitLayout::Permissionless => 18,
+                PoolInitLayout::WithConfig => 19,
+            };
+            decoded.push((variant.name, variant.discriminator, expected));
+        }
+        decoded
+    }
+
+    #[test]
+    fn short_data_is_missing_discriminator() {
+        for length in 0..IX_DISCRIMINATOR_SIZE {
+            let result = decode_instruction(&accounts(20), &SWAP_DISC[..length]);
+            assert!(matches!(result, Err(DecodeError::MissingDiscriminator(actual)) if actual == length));
+        }
+    }
+
+    #[test]
+    fn unknown_discriminator() {
+        let discriminator = [0xff; 8];
+        let result = decode_instruction(&accounts(20), &discriminator);
+        assert!(matches!(result, Err(DecodeError::UnknownDiscriminator(unknown)) if unknown == discriminator));
+    }
+
+    #[test]
+    fn other_instructions_by_name() {
+        let result = decode_instruction(&[], &LOCK_DISC);
+        assert!(matches!(result, Ok(MeteoraInstruction::Other("lock"))));
+    }
+
+    #[test]
+    fn too_few_accounts() {
+        // args are long enough for any variant, the accounts are checked first
+        let args = [0u8; 256];
+        for (name, discriminator, expected) in decoded() {
+            let result = decode_instruction(&accounts(expected - 1), &data(discriminator, &args));
+            match result {
+                Err(DecodeError::MissingAccounts {
+                    instruction,
+                    expected: needed,
+                    actual,
+                }) => {
+                    assert_eq!(instruction, name);
+                    assert_eq!(needed, expected);
+                    assert_eq!(actual, expected - 1);
+                }
+                other => panic!("{name}: expected MissingAccounts, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_args() {
+        for (name, discriminator, expected) in decoded() {
+            // add_balance_liquidity args are not read
+            if discriminator == ADD_BALANCE_LIQUIDITY_DISC {
+                continue;
+            }
+            let result = decode_instruction(&accounts(expected), &data(discriminator, &[1, 2, 3]));
+            match result {
+                Err(DecodeError::InvalidArgs { instruction, .. }) => assert_eq!(instruction, name),
+                other => panic!("{name}: expected InvalidArgs, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_swap() {
+        let accounts = accounts(SwapAccounts::LEN);
+        let args = borsh::to_vec(&SwapArgs {
+            in_amount: 1_000,
+            minimum_out_amount: 900,
+        })
+        .unwrap();
+        let Ok(MeteoraInstruction::Swap { accounts: swap, args }) =
+            decode_instruction(&accounts, &data(SWAP_DISC, &args))
+        else {
+            panic!("expected a swap");
+        };
+        assert_eq!(swap.pool, accounts[0].pubkey);
+        assert_eq!(swap.user, accounts[12].pubkey);
+        assert_eq!((args.in_amount, args.minimum_out_amount), (1_000, 900));
+    }
+}
@@ -1,26 +1,137 @@
 use crate::bench::Bench;
-use crate::core::extract_instructions;
-use crate::tx_senders::constants::{METEORA_POOLS_PROGRAM, WSOL_MINT};
+use crate::core::{ExtractedInstruction, account_keys, extract_instructions, token_balance_changes};
+use crate::filters::{PoolCandidate, SharedFilterPipeline};
+use crate::geyser::TransactionHandler;
+use crate::meteora::copy_trade::CopyTrader;
+use crate::meteora::events::{MeteoraEvent, parse_events};
+use crate::meteora::instructions::{
+    ADD_BALANCE_LIQUIDITY_DISC, ADD_IMBALANCE_LIQUIDITY_DISC, MeteoraInstruction, SWAP_DISC, decode_instruction,
+};
+use crate::meteora::pool_state::SharedPoolStateCache;
+use crate::meteora::wait_for_liquidity::LiquidityWatcher;
+use crate::policy::SharedSnipePolicy;
+use crate::token_2022::token_programs_by_mint;
+use crate::tx_senders::constants::{METEORA_POOLS_PROGRAM, TOKEN_PROGRAM, WSOL_MINT};
+use crate::tx_senders::transaction::BuyAccounts;
+use async_trait::async_trait;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_sdk::hash::Hash;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
 use solana_sdk::transaction::VersionedTransaction;
 use solana_transaction_status::TransactionStatusMeta;
+use tracing::{debug, info};
+
+pub mod copy_trade;
+pub mod events;
+pub mod instructions;
+pub mod pool_init;
+pub mod pool_state;
+pub mod wait_for_liquidity;
 
-pub const CREATE_IX_DISC: [u8; 8] = [0x30, 0x95, 0xdc, 0x82, 0x3d, 0x0b, 0x09, 0xb2];
 pub const IX_DISCRIMINATOR_SIZE: usize = 8;
+pub const BPS_DENOMINATOR: u64 = 10_000;
 
+// leading args of the config-based create instructions
 #[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
 pub struct CreateIxData {
     pub token_a: u64,
     pub token_b: u64,
 }
 
+/// Which side of the pool holds WSOL.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuoteSide {
+    A,
+    B,
+}
+
+/// Accounts needed to buy the non-WSOL token of a pool. Vault accounts keep the
+/// pool's A/B order, `token_mint` is always the token we buy.
 #[derive(Debug, Copy, Clone)]
 pub struct AccountsForBuy {
     pub pool: Pubkey,
-    pub a_token_mint: Pubkey,
+    pub token_mint: Pubkey,
+    pub quote_side: QuoteSide,
+    pub a_vault: Pubkey,
+    pub b_vault: Pubkey,
+    pub a_token_vault: Pubkey,
+    pub b_token_vault: Pubkey,
+    pub a_vault_lp_mint: Pubkey,
+    pub b_vault_lp_mint: Pubkey,
+    pub a_vault_lp: Pubkey,
+    pub b_vault_lp: Pubkey,
+    pub protocol_token_a_fee: Pubkey,
+    pub protocol_token_b_fee: Pubkey,
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+    // None when the fee lives in the pool config account
+    pub trade_fee_bps: Option<u64>,
+}
+
+impl AccountsForBuy {
+    // (quote reserve, token reserve)
+    pub fn reserves(&self) -> (u64, u64) {
+        match self.quote_side {
+            QuoteSide::A => (self.token_a_amount, self.token_b_amount),
+            QuoteSide::B => (self.token_b_amount, self.token_a_amount),
+        }
+    }
+
+    pub fn quote_buy(&self, amount_in: u64, default_trade_fee_bps: u64) -> u64 {
+        let (quote_reserve, token_reserve) = self.reserves();
+        let trade_fee_bps = self.trade_fee_bps.unwrap_or(default_trade_fee_bps);
+        quote_exact_in(amount_in, quote_reserve, token_reserve, trade_fee_bps)
+    }
+
+    pub fn quote_sell(&self, amount_in: u64, default_trade_fee_bps: u64) -> u64 {
+        let (quote_reserve, token_reserve) = self.reserves();
+        let trade_fee_bps = self.trade_fee_bps.unwrap_or(default_trade_fee_bps);
+        quote_exact_in(amount_in, token_reserve, quote_reserve, trade_fee_bps)
+    }
+
+    // token to WSOL through this pool, the protocol fee is charged on the token side
+    pub fn sell_swap(&self, amount_in: u64, expected_out: u64) -> AmmSwap {
+        let protocol_token_fee = match self.quote_side {
+            QuoteSide::A => self.protocol_token_b_fee,
+            QuoteSide::B => self.protocol_token_a_fee,
+        };
+        AmmSwap {
+            pool: self.pool,
+            input_mint: self.token_mint,
+            output_mint: WSOL_MINT,
+            a_vault: self.a_vault,
+            b_vault: self.b_vault,
+            a_token_vault: self.a_token_vault,
+            b_token_vault: self.b_token_vault,
+            a_vault_lp_mint: self.a_vault_lp_mint,
+            b_vault_lp_mint: self.b_vault_lp_mint,
+            a_vault_lp: self.a_vault_lp,
+            b_vault_lp: self.b_vault_lp,
+            protocol_token_fee,
+            amount_in,
+            expected_out,
+        }
+    }
+}
+
+// constant-product output for `amount_in`, the trade fee is taken from the input
+pub fn quote_exact_in(amount_in: u64, reserve_in: u64, reserve_out: u64, trade_fee_bps: u64) -> u64 {
+    let trade_fee_bps = trade_fee_bps.min(BPS_DENOMINATOR) as u128;
+    let amount_in = amount_in as u128 * (BPS_DENOMINATOR as u128 - trade_fee_bps) / BPS_DENOMINATOR as u128;
+    let denominator = reserve_in as u128 + amount_in;
+    if denominator == 0 {
+        return 0;
+    }
+    (reserve_out as u128 * amount_in / denominator) as u64
+}
+
+// A swap through an existing pool in either direction, `protocol_token_fee` belongs to the input side.
+#[derive(Debug, Copy, Clone)]
+pub struct AmmSwap {
+    pub pool: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
     pub a_vault: Pubkey,
     pub b_vault: Pubkey,
     pub a_token_vault: Pubkey,
@@ -30,82 +141,396 @@ pub struct AccountsForBuy {
     pub a_vault_lp: Pubkey,
     pub b_vault_lp: Pubkey,
     pub protocol_token_fee: Pubkey,
+    pub amount_in: u64,
+    // before slippage
+    pub expected_out: u64,
+}
+
+impl AmmSwap {
+    // at most `max_in` in, the expected output shrinks with it
+    pub fn capped(self, max_in: u64) -> Self {
+        if self.amount_in <= max_in {
+            return self;
+        }
+        AmmSwap {
+            amount_in: max_in,
+            expected_out: (self.expected_out as u128 * max_in as u128 / self.amount_in as u128) as u64,
+            ..self
+        }
+    }
 }
 
 pub struct MeteoraController {
     bench: Bench,
-
-    is_buy: bool,
+    policy: SharedSnipePolicy,
+    filters: SharedFilterPipeline,
+    copy_trader: Option<CopyTrader>,
+    liquidity_watcher: Option<LiquidityWatcher>,
+    pool_state: SharedPoolStateCache,
 }
 
 impl MeteoraController {
-    pub fn new(bench: Bench) -> Self {
-        MeteoraController { bench, is_buy: false }
+    pub fn new(
+        bench: Bench,
+        policy: SharedSnipePolicy,
+        filters: SharedFilterPipeline,
+        copy_trader: Option<CopyTrader>,
+        liquidity_watcher: Option<LiquidityWatcher>,
+        pool_state: SharedPoolStateCache,
+    ) -> Self {
+        MeteoraController {
+            bench,
+            policy,
+            filters,
+            copy_trader,
+            liquidity_watcher,
+            pool_state,
+        }
+    }
+
+    async fn on_liquidity_added(
+        &mut self,
+        pool: Pubkey,
+        deposit: Option<(u64, u64)>,
+        slot: u64,
+        recent_blockhash: Hash,
+    ) {
+        let (Some(liquidity_watcher), Some((token_a_amount, token_b_amount))) =
+            (self.liquidity_watcher.as_mut(), deposit)
+        else {
+            return;
+        };
+
+        if let Some(pending) = liquidity_watcher.on_liquidity_added(&pool, token_a_amount, token_b_amount) {
+            info!(
+                "liquidity added to pool {} after {} slots",
+                pool,
+                slot.saturating_sub(pending.armed_slot)
+            );
+            self.snipe(pending.accounts_for_buy, pending.creator, slot, recent_blockhash).await;
+        }
+    }
+
+    // Runs the filters and the policy, then sends the buy and keeps the pool state
+    // cached for as long as the position is held.
+    async fn snipe(&self, mut accounts_for_buy: AccountsForBuy, creator: Pubkey, slot: u64, recent_blockhash: Hash) {
+        // account updates can be ahead of the transaction that triggered the snipe
+        if let Some(state) = self.pool_state.state(&accounts_for_buy.pool).filter(|state| state.slot >= slot) {
+            debug!(
+                "pool {} reserves {}/{} at slot {}, virtual price {:?}",
+                accounts_for_buy.pool, state.token_a_amount, state.token_b_amount, state.slot, state.virtual_price
+            );
+            if !state.enabled {
+                info!("skipping pool {}, disabled", accounts_for_buy.pool);
+                self.pool_state.unwatch(&accounts_for_buy.pool);
+                return;
+            }
+            accounts_for_buy.token_a_amount = state.token_a_amount;
+            accounts_for_buy.token_b_amount = state.token_b_amount;
+            accounts_for_buy.trade_fee_bps = Some(state.trade_fee_bps);
+        }
+
+        let candidate = PoolCandidate {
+            pool: accounts_for_buy.pool,
+            token_mint: accounts_for_buy.token_mint,
+            creator,
+            quote_liquidity: Some(accounts_for_buy.reserves().0),
+        };
+        if let Err(rejection) = self.filters.evaluate(&candidate) {
+            info!(
+                "skipping pool {}, rejected by {} filter: {}",
+                candidate.pool, rejection.filter, rejection.reason
+            );
+            self.pool_state.unwatch(&candidate.pool);
+            return;
+        }
+
+        let acquired = self.policy.lock().unwrap().try_acquire(accounts_for_buy.token_mint, creator);
+        if let Err(rejection) = acquired {
+            info!("skipping pool {}: {}", accounts_for_buy.pool, rejection);
+            self.pool_state.unwatch(&accounts_for_buy.pool);
+            return;
+        }
+
+        self.pool_state.watch(&accounts_for_buy);
+        let sent = self.bench.clone().send_buy_tx(recent_blockhash, BuyAccounts::Amm(accounts_for_buy)).await;
+        self.bench.track_buy(self.policy.clone(), accounts_for_buy.token_mint, sent);
     }
+}
 
-    pub async fn transaction_handler(
+#[async_trait]
+impl TransactionHandler for MeteoraController {
+    async fn transaction_handler(
         &mut self,
         _signature: Signature,
         transaction: VersionedTransaction,
         meta: TransactionStatusMeta,
         _is_vote: bool,
-        _slot: u64,
+        slot: u64,
     ) -> anyhow::Result<()> {
-        let instructions: Vec<solana_sdk::instruction::Instruction> = extract_instructions(meta, transaction.clone())?;
+        if let Some(liquidity_watcher) = self.liquidity_watcher.as_mut() {
+            for pool in liquidity_watcher.expire(slot) {
+                info!("giving up on pool {}, no liquidity after {} slots", pool, slot);
+                self.pool_state.unwatch(&pool);
+            }
+        }
+        // positions released or cancelled since the last transaction are no longer streamed
+        {
+            let policy = self.policy.lock().unwrap();
+            let liquidity_watcher = self.liquidity_watcher.as_ref();
+            self.pool_state.retain(|pool, mint| {
+                policy.is_open(mint) || liquidity_watcher.is_some_and(|watcher| watcher.is_armed(pool))
+            });
+        }
+
+        let token_programs = token_programs_by_mint(&meta);
+        let balances = match self.copy_trader {
+            Some(_) => token_balance_changes(&meta, &account_keys(&meta, &transaction)),
+            None => Default::default(),
+        };
+        let events = meta.log_messages.as_deref().map(parse_events).unwrap_or_default();
+        for event in &events {
+            debug!("pool program event {:?}", event);
+        }
+        let mut swap_events = events.iter().filter_map(|event| match event {
+            MeteoraEvent::Swap(swap_event) => Some(swap_event),
+            _ => None,
+        });
+        // emitted by both add_balance_liquidity and add_imbalance_liquidity
+        let mut add_liquidity_events = events.iter().filter_map(|event| match event {
+            MeteoraEvent::AddLiquidity(add_liquidity_event) => Some(add_liquidity_event),
+            _ => None,
+        });
 
-        if !self.is_buy {
-            for instruction in instructions {
-                if instruction.program_id == METEORA_POOLS_PROGRAM {
-                    let ix_discriminator: [u8; 8] = instruction.data[0..IX_DISCRIMINATOR_SIZE].try_into()?;
+        let recent_blockhash: Hash = *transaction.message.recent_blockhash();
+        let instructions: Vec<ExtractedInstruction> = extract_instructions(meta, transaction.clone())?;
+
+        // creates made through a CPI (launchpads, aggregators) are matched the same way as top-level ones
+        for extracted in &instructions {
+            let instruction = &extracted.instruction;
+            if instruction.program_id != METEORA_POOLS_PROGRAM {
+                continue;
+            }
 
-                    if ix_discriminator != CREATE_IX_DISC {
+            let pool_init = match decode_instruction(&instruction.accounts, &instruction.data) {
+                Ok(MeteoraInstruction::PoolInit(pool_init)) => pool_init,
+                Ok(MeteoraInstruction::Swap { accounts, args }) => {
+                    let swap_event = swap_events.next();
+                    let Some(copy_trader) = self.copy_trader.as_ref().filter(|trader| trader.tracks(&accounts.user))
+                    else {
                         continue;
+                    };
+
+                    match copy_trader.mirror(&accounts, &args, swap_event, &balances) {
+                        Ok(mut swap) => {
+                            // a pool we hold is quoted from its live reserves, once they include the tracked swap
+                            if let Some(expected_out) = self
+                                .pool_state
+                                .state(&swap.pool)
+                                .filter(|state| state.slot >= slot)
+                                .and_then(|state| state.quote_exact_in(&swap.input_mint, swap.amount_in))
+                            {
+                                swap.expected_out = expected_out;
+                            }
+                            // a mirrored buy is a position like a snipe, sells are left to the position tracker
+                            let buy = swap.input_mint == WSOL_MINT;
+                            if buy {
+                                if let Err(rejection) =
+                                    self.policy.lock().unwrap().try_acquire_mirror(swap.output_mint, swap.amount_in)
+                                {
+                                    info!("not mirroring swap of {}: {}", accounts.user, rejection);
+                                    continue;
+                                }
+                            }
+                            info!(
+                                "mirroring swap of {} in pool {}: {} {} -> {}",
+                                accounts.user, swap.pool, swap.amount_in, swap.input_mint, swap.output_mint
+                            );
+                            let sent =
+                                self.bench.clone().send_buy_tx(recent_blockhash, BuyAccounts::AmmSwap(swap)).await;
+                            if buy {
+                                self.bench.track_buy(self.policy.clone(), swap.output_mint, sent);
+                            }
+                        }
+                        Err(reason) => info!("not mirroring swap of {}: {}", accounts.user, reason),
+                    }
+                    continue;
+                }
+                Ok(MeteoraInstruction::AddBalanceLiquidity { accounts }) => {
+                    let deposit = add_liquidity_events.next().map(|event| (event.token_a_amount, event.token_b_amount));
+                    self.on_liquidity_added(accounts.pool, deposit, slot, recent_blockhash).await;
+                    continue;
+                }
+                Ok(MeteoraInstruction::AddImbalanceLiquidity { accounts, args }) => {
+                    let deposit =
+                        add_liquidity_events.next().map_or((args.token_a_amount, args.token_b_amount), |event| {
+                            (event.token_a_amount, event.token_b_amount)
+                        });
+                    self.on_liquidity_added(accounts.pool, Some(deposit), slot, recent_blockhash).await;
+                    continue;
+                }
+                Ok(MeteoraInstruction::Other(name)) => {
+                    debug!("ignoring pool program instruction {}: {}", extracted.outer_index, name);
+                    continue;
+                }
+                Err(error) => {
+                    debug!(
+                        "cannot decode pool program instruction {}: {}",
+                        extracted.outer_index, error
+                    );
+                    // the instruction still emitted its event, skip it to keep the pairing of the ones after it
+                    match instruction.data.get(..IX_DISCRIMINATOR_SIZE) {
+                        Some(disc) if disc == SWAP_DISC => {
+                            swap_events.next();
+                        }
+                        Some(disc) if disc == ADD_BALANCE_LIQUIDITY_DISC || disc == ADD_IMBALANCE_LIQUIDITY_DISC => {
+                            add_liquidity_events.next();
+                        }
+                        _ => {}
                     }
+                    continue;
+                }
+            };
 
-                    let a_token_mint = instruction.accounts[3].pubkey;
-                    let b_token_mint = instruction.accounts[4].pubkey;
+            let Some(accounts_for_buy) = pool_init.accounts_for_buy() else {
+                continue;
+            };
 
+            // The vault program moves both sides with a single token program, so only SPL
+            // Token mints can be swapped: every Token-2022 launch on the dynamic AMM is skipped.
+            let token_program = token_programs.get(&accounts_for_buy.token_mint).copied().unwrap_or(TOKEN_PROGRAM);
+            if token_program != TOKEN_PROGRAM {
+                info!(
+                    "skipping pool {}: mint {} is owned by {}",
+                    accounts_for_buy.pool, accounts_for_buy.token_mint, token_program
+                );
+                continue;
+            }
 
-                    //TODO: sort A/B tokens
-                    if  b_token_mint != WSOL_MINT {
-                        continue;
-                    }
+            info!(
+                "pool {} created by {} via {} at instruction {} stack height {}",
+                accounts_for_buy.pool,
+                pool_init.accounts.payer,
+                pool_init.variant,
+                extracted.outer_index,
+                extracted.stack_height
+            );
 
-                    let pool = instruction.accounts[0].pubkey;
-                    let a_vault = instruction.accounts[5].pubkey;
-                    let b_vault = instruction.accounts[6].pubkey;
-                    let a_token_vault = instruction.accounts[7].pubkey;
-                    let b_token_vault = instruction.accounts[8].pubkey;
-                    let a_vault_lp_mint = instruction.accounts[9].pubkey;
-                    let b_vault_lp_mint = instruction.accounts[10].pubkey;
-                    let a_vault_lp = instruction.accounts[11].pubkey;
-                    let b_vault_lp = instruction.accounts[12].pubkey;
-                    let protocol_token_fee = instruction.accounts[17].pubkey; // user_source_token in swap
-
-                    let recent_blockhash: Hash = *transaction.message.recent_blockhash();
-                    self.is_buy = true;
-                    self.bench
-                        .clone()
-                        .send_buy_tx(
-                            recent_blockhash,
-                                AccountsForBuy {
-                                    pool,
-                                    a_token_mint,
-                                    a_vault,
-                                    b_vault,
-                                    a_token_vault,
-                                    b_token_vault,
-                                    a_vault_lp_mint,
-                                    b_vault_lp_mint,
-                                    a_vault_lp,
-                                    b_vault_lp,
-                                    protocol_token_fee,
-                                },
-                        )
-                        .await;
+            if let Some(liquidity_watcher) = self.liquidity_watcher.as_mut() {
+                if liquidity_watcher.should_wait(&accounts_for_buy) {
+                    info!(
+                        "pool {} created without enough liquidity, waiting for a deposit",
+                        accounts_for_buy.pool
+                    );
+                    self.pool_state.watch(&accounts_for_buy);
+                    liquidity_watcher.arm(accounts_for_buy, pool_init.accounts.payer, slot);
+                    continue;
                 }
+            } else if pool_init.token_a_amount == 0 || pool_init.token_b_amount == 0 {
+                info!("skipping pool created without liquidity");
+                continue;
             }
+
+            self.snipe(accounts_for_buy, pool_init.accounts.payer, slot, recent_blockhash).await;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn accounts_for_buy(quote_side: QuoteSide, token_a_amount: u64, token_b_amount: u64) -> AccountsForBuy {
+        AccountsForBuy {
+            pool: Pubkey::new_unique(),
+            token_mint: Pubkey::new_unique(),
+            quote_side,
+            a_vault: Pubkey::new_unique(),
+            b_vault: Pubkey::new_unique(),
+            a_token_vault: Pubkey::new_unique(),
+            b_token_vault: Pubkey::new_unique(),
+            a_vault_lp_mint: Pubkey::new_unique(),
+            b_vault_lp_mint: Pubkey::new_unique(),
+            a_vault_lp: Pubkey::new_unique(),
+            b_vault_lp: Pubkey::new_unique(),
+            protocol_token_a_fee: Pubkey::new_unique(),
+            protocol_token_b_fee: Pubkey::new_unique(),
+            token_a_amount,
+            token_b_amount,
+            trade_fee_bps: None,
+        }
+    }
+
+    #[test]
+    fn constant_product_quote() {
+        // 1 in against 10/1000 reserves: 1000 * 1 / 11
+        assert_eq!(quote_exact_in(1, 10, 1_000, 0), 90);
+        assert_eq!(quote_exact_in(1_000_000, 10_000_000, 1_000_000_000, 0), 90_909_090);
+        // the fee comes off the input first
+        assert_eq!(
+            quote_exact_in(1_000_000, 10_000_000, 1_000_000_000, 100),
+            quote_exact_in(990_000, 10_000_000, 1_000_000_000, 0)
+        );
+    }
+
+    #[test]
+    fn quote_without_reserves_or_input() {
+        assert_eq!(quote_exact_in(0, 0, 0, 25), 0);
+        assert_eq!(quote_exact_in(1_000, 0, 0, 25), 0);
+        assert_eq!(quote_exact_in(0, 1_000, 1_000, 25), 0);
+        // a fee above 100% takes the whole input
+        assert_eq!(quote_exact_in(1_000, 1_000, 1_000, 20_000), 0);
+    }
+
+    #[test]
+    fn quote_follows_the_wsol_side() {
+        let wsol_a = accounts_for_buy(QuoteSide::A, 1_000_000, 5_000);
+        assert_eq!(wsol_a.reserves(), (1_000_000, 5_000));
+        let wsol_b = accounts_for_buy(QuoteSide::B, 5_000, 1_000_000);
+        assert_eq!(wsol_b.reserves(), (1_000_000, 5_000));
+        assert_eq!(
+            wsol_b.quote_buy(100_000, 25),
+            quote_exact_in(100_000, 1_000_000, 5_000, 25)
+        );
+        assert_eq!(wsol_b.quote_sell(100, 25), quote_exact_in(100, 5_000, 1_000_000, 25));
+    }
+
+    #[test]
+    fn pool_fee_overrides_the_default() {
+        let mut accounts_for_buy = accounts_for_buy(QuoteSide::A, 1_000_000, 5_000_000);
+        assert_eq!(
+            accounts_for_buy.quote_buy(10_000, 25),
+            quote_exact_in(10_000, 1_000_000, 5_000_000, 25)
+        );
+        accounts_for_buy.trade_fee_bps = Some(100);
+        assert_eq!(
+            accounts_for_buy.quote_buy(10_000, 25),
+            quote_exact_in(10_000, 1_000_000, 5_000_000, 100)
+        );
+    }
+
+    #[test]
+    fn capped_swap_scales_the_expected_output() {
+        let swap = AmmSwap {
+            pool: Pubkey::new_unique(),
+            input_mint: Pubkey::new_unique(),
+            output_mint: WSOL_MINT,
+            a_vault: Pubkey::new_unique(),
+            b_vault: Pubkey::new_unique(),
+            a_token_vault: Pubkey::new_unique(),
+            b_token_vault: Pubkey::new_unique(),
+            a_vault_lp_mint: Pubkey::new_unique(),
+            b_vault_lp_mint: Pubkey::new_unique(),
+            a_vault_lp: Pubkey::new_unique(),
+            b_vault_lp: Pubkey::new_unique(),
+            protocol_token_fee: Pubkey::new_unique(),
+            amount_in: 1_000,
+            expected_out: 300,
+        };
+        let capped = swap.capped(250);
+        assert_eq!((capped.amount_in, capped.expected_out), (250, 75));
+        let uncapped = swap.capped(5_000);
+        assert_eq!((uncapped.amount_in, uncapped.expected_out), (1_000, 300));
+    }
+}
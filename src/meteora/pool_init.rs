@@ -0,0 +1,312 @@
+use crate::meteora::instructions::{DecodeError, check_accounts, decode_args};
+use crate::meteora::{AccountsForBuy, BPS_DENOMINATOR, CreateIxData, IX_DISCRIMINATOR_SIZE, QuoteSide};
+use crate::tx_senders::constants::WSOL_MINT;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+
+pub const INITIALIZE_PERMISSIONLESS_POOL_DISC: [u8; 8] = [0x76, 0xad, 0x29, 0x9d, 0xad, 0x48, 0x61, 0x67];
+pub const INITIALIZE_PERMISSIONLESS_POOL_WITH_FEE_TIER_DISC: [u8; 8] = [0x06, 0x87, 0x44, 0x93, 0xe5, 0x52, 0xa9, 0x71];
+pub const INITIALIZE_CONSTANT_PRODUCT_POOL_WITH_CONFIG_DISC: [u8; 8] = [0x07, 0xa6, 0x8a, 0xab, 0xce, 0xab, 0xec, 0xf4];
+pub const INITIALIZE_CONSTANT_PRODUCT_POOL_WITH_CONFIG2_DISC: [u8; 8] =
+    [0x30, 0x95, 0xdc, 0x82, 0x3d, 0x0b, 0x09, 0xb2];
+pub const INITIALIZE_CUSTOMIZABLE_CONSTANT_PRODUCT_POOL_DISC: [u8; 8] =
+    [0x91, 0x18, 0xac, 0xc2, 0xdb, 0x7d, 0x03, 0xbe];
+
+// fee of permissionless constant-product pools that do not pick a fee tier
+pub const DEFAULT_CONSTANT_PRODUCT_TRADE_FEE_BPS: u64 = 25;
+// customizable pools express the fee as a numerator over this denominator
+pub const FEE_DENOMINATOR: u64 = 100_000;
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone, PartialEq, Eq)]
+pub enum DepegType {
+    None,
+    Marinade,
+    Lido,
+    SplStake,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct Depeg {
+    pub base_virtual_price: u64,
+    pub base_cache_updated: u64,
+    pub depeg_type: DepegType,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct TokenMultiplier {
+    pub token_a_multiplier: u64,
+    pub token_b_multiplier: u64,
+    pub precision_factor: u8,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub enum CurveType {
+    ConstantProduct,
+    Stable {
+        amp: u64,
+        token_multiplier: TokenMultiplier,
+        depeg: Depeg,
+        last_amp_updated_timestamp: u64,
+    },
+}
+
+impl CurveType {
+    pub fn is_stable(&self) -> bool {
+        matches!(self, CurveType::Stable { .. })
+    }
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct PermissionlessPoolIxData {
+    pub curve_type: CurveType,
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct PermissionlessPoolWithFeeTierIxData {
+    pub curve_type: CurveType,
+    pub trade_fee_bps: u64,
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct CustomizableParams {
+    pub trade_fee_numerator: u32,
+    pub activation_point: Option<u64>,
+    pub has_alpha_vault: bool,
+    pub activation_type: u8,
+    pub padding: [u8; 90],
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct CustomizablePoolIxData {
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+    pub params: CustomizableParams,
+}
+
+// Account layout shared by every pool-initialization instruction. Config-based
+// variants insert the config account right after the pool, shifting the rest by one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PoolInitLayout {
+    Permissionless,
+    WithConfig,
+}
+
+impl PoolInitLayout {
+    fn offset(self) -> usize {
+        match self {
+            PoolInitLayout::Permissionless => 0,
+            PoolInitLayout::WithConfig => 1,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PoolInitAccounts {
+    pub pool: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub a_vault: Pubkey,
+    pub b_vault: Pubkey,
+    pub a_token_vault: Pubkey,
+    pub b_token_vault: Pubkey,
+    pub a_vault_lp_mint: Pubkey,
+    pub b_vault_lp_mint: Pubkey,
+    pub a_vault_lp: Pubkey,
+    pub b_vault_lp: Pubkey,
+    pub protocol_token_a_fee: Pubkey,
+    pub protocol_token_b_fee: Pubkey,
+    pub payer: Pubkey,
+}
+
+impl PoolInitAccounts {
+    pub fn decode(
+        instruction: &'static str,
+        layout: PoolInitLayout,
+        accounts: &[AccountMeta],
+    ) -> Result<Self, DecodeError> {
+        let offset = layout.offset();
+        // pool [+ config] + 17 accounts up to and including the payer
+        let accounts = check_accounts(instruction, accounts, offset + 18)?;
+        let account = |index: usize| accounts[offset + index].pubkey;
+
+        Ok(PoolInitAccounts {
+            pool: accounts[0].pubkey,
+            token_a_mint: account(2),
+            token_b_mint: account(3),
+            a_vault: account(4),
+            b_vault: account(5),
+            a_token_vault: account(6),
+            b_token_vault: account(7),
+            a_vault_lp_mint: account(8),
+            b_vault_lp_mint: account(9),
+            a_vault_lp: account(10),
+            b_vault_lp: account(11),
+            protocol_token_a_fee: account(15),
+            protocol_token_b_fee: account(16),
+            payer: account(17),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PoolInitArgs {
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+    // None when the fee lives in the pool config account
+    pub trade_fee_bps: Option<u64>,
+    pub stable: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PoolInit {
+    pub variant: &'static str,
+    pub accounts: PoolInitAccounts,
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+    pub trade_fee_bps: Option<u64>,
+    pub stable: bool,
+}
+
+impl PoolInit {
+    // None when neither side of the pool is WSOL, and for stable curves
+    // which the constant-product quote cannot price
+    pub fn accounts_for_buy(&self) -> Option<AccountsForBuy> {
+        if self.stable {
+            return None;
+        }
+        let accounts = &self.accounts;
+        let (token_mint, quote_side) = if accounts.token_b_mint == WSOL_MINT {
+            (accounts.token_a_mint, QuoteSide::B)
+        } else if accounts.token_a_mint == WSOL_MINT {
+            (accounts.token_b_mint, QuoteSide::A)
+        } else {
+            return None;
+        };
+
+        Some(AccountsForBuy {
+            pool: accounts.pool,
+            token_mint,
+            quote_side,
+            a_vault: accounts.a_vault,
+            b_vault: accounts.b_vault,
+            a_token_vault: accounts.a_token_vault,
+            b_token_vault: accounts.b_token_vault,
+            a_vault_lp_mint: accounts.a_vault_lp_mint,
+            b_vault_lp_mint: accounts.b_vault_lp_mint,
+            a_vault_lp: accounts.a_vault_lp,
+            b_vault_lp: accounts.b_vault_lp,
+            protocol_token_a_fee: accounts.protocol_token_a_fee,
+            protocol_token_b_fee: accounts.protocol_token_b_fee,
+            token_a_amount: self.token_a_amount,
+            token_b_amount: self.token_b_amount,
+            trade_fee_bps: self.trade_fee_bps,
+        })
+    }
+}
+
+pub struct PoolInitVariant {
+    pub name: &'static str,
+    pub discriminator: [u8; 8],
+    pub layout: PoolInitLayout,
+    pub decode_args: fn(&'static str, &[u8]) -> Result<PoolInitArgs, DecodeError>,
+}
+
+impl PoolInitVariant {
+    pub fn decode(&'static self, accounts: &[AccountMeta], data: &[u8]) -> Result<PoolInit, DecodeError> {
+        let accounts = PoolInitAccounts::decode(self.name, self.layout, accounts)?;
+        let args = (self.decode_args)(self.name, data.get(IX_DISCRIMINATOR_SIZE..).unwrap_or_default())?;
+        Ok(PoolInit {
+            variant: self.name,
+            accounts,
+            token_a_amount: args.token_a_amount,
+            token_b_amount: args.token_b_amount,
+            trade_fee_bps: args.trade_fee_bps,
+            stable: args.stable,
+        })
+    }
+}
+
+pub static POOL_INIT_VARIANTS: [PoolInitVariant; 5] = [
+    PoolInitVariant {
+        name: "initialize_permissionless_pool",
+        discriminator: INITIALIZE_PERMISSIONLESS_POOL_DISC,
+        layout: PoolInitLayout::Permissionless,
+        decode_args: decode_permissionless_args,
+    },
+    PoolInitVariant {
+        name: "initialize_permissionless_pool_with_fee_tier",
+        discriminator: INITIALIZE_PERMISSIONLESS_POOL_WITH_FEE_TIER_DISC,
+        layout: PoolInitLayout::Permissionless,
+        decode_args: decode_fee_tier_args,
+    },
+    PoolInitVariant {
+        name: "initialize_permissionless_constant_product_pool_with_config",
+        discriminator: INITIALIZE_CONSTANT_PRODUCT_POOL_WITH_CONFIG_DISC,
+        layout: PoolInitLayout::WithConfig,
+        decode_args: decode_with_config_args,
+    },
+    PoolInitVariant {
+        name: "initialize_permissionless_constant_product_pool_with_config2",
+        discriminator: INITIALIZE_CONSTANT_PRODUCT_POOL_WITH_CONFIG2_DISC,
+        layout: PoolInitLayout::WithConfig,
+        decode_args: decode_with_config_args,
+    },
+    PoolInitVariant {
+        name: "initialize_customizable_permissionless_constant_product_pool",
+        discriminator: INITIALIZE_CUSTOMIZABLE_CONSTANT_PRODUCT_POOL_DISC,
+        layout: PoolInitLayout::Permissionless,
+        decode_args: decode_customizable_args,
+    },
+];
+
+pub fn find_pool_init_variant(data: &[u8]) -> Option<&'static PoolInitVariant> {
+    let discriminator = data.get(..IX_DISCRIMINATOR_SIZE)?;
+    POOL_INIT_VARIANTS.iter().find(|variant| variant.discriminator == discriminator)
+}
+
+fn decode_permissionless_args(name: &'static str, data: &[u8]) -> Result<PoolInitArgs, DecodeError> {
+    let args: PermissionlessPoolIxData = decode_args(name, data)?;
+    Ok(PoolInitArgs {
+        token_a_amount: args.token_a_amount,
+        token_b_amount: args.token_b_amount,
+        trade_fee_bps: Some(DEFAULT_CONSTANT_PRODUCT_TRADE_FEE_BPS),
+        stable: args.curve_type.is_stable(),
+    })
+}
+
+fn decode_fee_tier_args(name: &'static str, data: &[u8]) -> Result<PoolInitArgs, DecodeError> {
+    let args: PermissionlessPoolWithFeeTierIxData = decode_args(name, data)?;
+    Ok(PoolInitArgs {
+        token_a_amount: args.token_a_amount,
+        token_b_amount: args.token_b_amount,
+        trade_fee_bps: Some(args.trade_fee_bps),
+        stable: args.curve_type.is_stable(),
+    })
+}
+
+// with_config2 appends an activation point, only the leading amounts are read
+fn decode_with_config_args(name: &'static str, data: &[u8]) -> Result<PoolInitArgs, DecodeError> {
+    let args: CreateIxData = decode_args(name, data)?;
+    Ok(PoolInitArgs {
+        token_a_amount: args.token_a,
+        token_b_amount: args.token_b,
+        trade_fee_bps: None,
+        stable: false,
+    })
+}
+
+fn decode_customizable_args(name: &'static str, data: &[u8]) -> Result<PoolInitArgs, DecodeError> {
+    let args: CustomizablePoolIxData = decode_args(name, data)?;
+    let trade_fee_bps = args.params.trade_fee_numerator as u64 * BPS_DENOMINATOR / FEE_DENOMINATOR;
+    Ok(PoolInitArgs {
+        token_a_amount: args.token_a_amount,
+        token_b_amount: args.token_b_amount,
+        trade_fee_bps: Some(trade_fee_bps),
+        stable: false,
+    })
+}
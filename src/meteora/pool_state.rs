@@ -0,0 +1,544 @@
+use crate::geyser::AccountHandler;
+use crate::meteora::{AccountsForBuy, BPS_DENOMINATOR, IX_DISCRIMINATOR_SIZE, QuoteSide, quote_exact_in};
+use crate::tx_senders::constants::WSOL_MINT;
+use anyhow::{Context, bail};
+use borsh::BorshDeserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+pub type SharedPoolStateCache = Arc<PoolStateCache>;
+
+const LOCKED_PROFIT_DEGRADATION_DENOMINATOR: u128 = 1_000_000_000_000;
+// spl token account amount and mint supply
+const TOKEN_ACCOUNT_AMOUNT_OFFSET: usize = 64;
+const MINT_SUPPLY_OFFSET: usize = 36;
+
+// Leading fields of the pool account, up to the fees.
+#[derive(Debug, BorshDeserialize)]
+struct PoolAccount {
+    lp_mint: Pubkey,
+    token_a_mint: Pubkey,
+    token_b_mint: Pubkey,
+    a_vault: Pubkey,
+    b_vault: Pubkey,
+    a_vault_lp: Pubkey,
+    b_vault_lp: Pubkey,
+    _a_vault_lp_bump: u8,
+    enabled: bool,
+    protocol_token_a_fee: Pubkey,
+    protocol_token_b_fee: Pubkey,
+    _fee_last_updated_at: u64,
+    _padding0: [u8; 24],
+    trade_fee_numerator: u64,
+    trade_fee_denominator: u64,
+}
+
+impl PoolAccount {
+    fn trade_fee_bps(&self) -> u64 {
+        match self.trade_fee_denominator {
+            0 => 0,
+            denominator => self.trade_fee_numerator * BPS_DENOMINATOR / denominator,
+        }
+    }
+}
+
+// Vault program account, the profit reported by strategies unlocks linearly.
+#[derive(Debug, BorshDeserialize)]
+struct VaultAccount {
+    _enabled: u8,
+    _vault_bump: u8,
+    _token_vault_bump: u8,
+    total_amount: u64,
+    token_vault: Pubkey,
+    _fee_vault: Pubkey,
+    _token_mint: Pubkey,
+    lp_mint: Pubkey,
+    _strategies: [Pubkey; 30],
+    _base: Pubkey,
+    _admin: Pubkey,
+    _operator: Pubkey,
+    last_updated_locked_profit: u64,
+    last_report: u64,
+    locked_profit_degradation: u64,
+}
+
+impl VaultAccount {
+    // total amount minus the profit still locked at `now`
+    fn unlocked_amount(&self, now: u64) -> u64 {
+        let duration = now.saturating_sub(self.last_report) as u128;
+        let locked_fund_ratio = duration * self.locked_profit_degradation as u128;
+        if locked_fund_ratio > LOCKED_PROFIT_DEGRADATION_DENOMINATOR {
+            return self.total_amount;
+        }
+        let locked_profit = self.last_updated_locked_profit as u128
+            * (LOCKED_PROFIT_DEGRADATION_DENOMINATOR - locked_fund_ratio)
+            / LOCKED_PROFIT_DEGRADATION_DENOMINATOR;
+        self.total_amount.saturating_sub(locked_profit as u64)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AccountKind {
+    Pool,
+    Vault,
+    TokenAccount,
+    Mint,
+}
+
+#[derive(Debug)]
+enum AccountState {
+    Pool(Box<PoolAccount>),
+    Vault(Box<VaultAccount>),
+    // token account amount or mint supply
+    Amount(u64),
+}
+
+fn decode_account(kind: AccountKind, data: &[u8]) -> Option<AccountState> {
+    let read_u64 = |offset: usize| Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?));
+    match kind {
+        AccountKind::Pool => {
+            let pool_account = PoolAccount::deserialize(&mut data.get(IX_DISCRIMINATOR_SIZE..)?).ok()?;
+            Some(AccountState::Pool(Box::new(pool_account)))
+        }
+        AccountKind::Vault => {
+            let vault_account = VaultAccount::deserialize(&mut data.get(IX_DISCRIMINATOR_SIZE..)?).ok()?;
+            Some(AccountState::Vault(Box::new(vault_account)))
+        }
+        AccountKind::TokenAccount => read_u64(TOKEN_ACCOUNT_AMOUNT_OFFSET).map(AccountState::Amount),
+        AccountKind::Mint => read_u64(MINT_SUPPLY_OFFSET).map(AccountState::Amount),
+    }
+}
+
+// Accounts the reserves of a pool are derived from.
+#[derive(Debug, Copy, Clone)]
+struct WatchedPool {
+    // the token held or about to be bought
+    token_mint: Pubkey,
+    a_vault: Pubkey,
+    b_vault: Pubkey,
+    a_vault_lp: Pubkey,
+    b_vault_lp: Pubkey,
+    a_vault_lp_mint: Pubkey,
+    b_vault_lp_mint: Pubkey,
+}
+
+impl WatchedPool {
+    fn accounts(&self) -> [(Pubkey, AccountKind); 6] {
+        [
+            (self.a_vault, AccountKind::Vault),
+            (self.b_vault, AccountKind::Vault),
+            (self.a_vault_lp, AccountKind::TokenAccount),
+            (self.b_vault_lp, AccountKind::TokenAccount),
+            (self.a_vault_lp_mint, AccountKind::Mint),
+            (self.b_vault_lp_mint, AccountKind::Mint),
+        ]
+    }
+}
+
+/// Reserves of a dynamic AMM pool as of the latest account updates.
+#[derive(Debug, Copy, Clone)]
+pub struct PoolState {
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+    // None until the pool LP mint has been seen
+    pub virtual_price: Option<f64>,
+    pub trade_fee_bps: u64,
+    pub enabled: bool,
+    // latest slot among the accounts it was derived from
+    pub slot: u64,
+}
+
+impl PoolState {
+    // None when the mint is neither side of the pool
+    pub fn quote_exact_in(&self, input_mint: &Pubkey, amount_in: u64) -> Option<u64> {
+        let (reserve_in, reserve_out) = if *input_mint == self.token_a_mint {
+            (self.token_a_amount, self.token_b_amount)
+        } else if *input_mint == self.token_b_mint {
+            (self.token_b_amount, self.token_a_amount)
+        } else {
+            return None;
+        };
+        Some(quote_exact_in(amount_in, reserve_in, reserve_out, self.trade_fee_bps))
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    pools: HashMap<Pubkey, WatchedPool>,
+    kinds: HashMap<Pubkey, AccountKind>,
+    accounts: HashMap<Pubkey, (AccountState, u64)>,
+}
+
+impl Inner {
+    // every account of the watched pools, vaults are shared by all pools of a mint
+    fn rebuild(&mut self) {
+        let mut kinds = HashMap::new();
+        for (pool, watched) in &self.pools {
+            kinds.insert(*pool, AccountKind::Pool);
+            if let Some((AccountState::Pool(pool_account), _)) = self.accounts.get(pool) {
+                kinds.insert(pool_account.lp_mint, AccountKind::Mint);
+            }
+            kinds.extend(watched.accounts());
+        }
+        self.accounts.retain(|account, _| kinds.contains_key(account));
+        self.kinds = kinds;
+    }
+
+    fn amount(&self, account: &Pubkey) -> Option<(u64, u64)> {
+        match self.accounts.get(account)? {
+            (AccountState::Amount(amount), slot) => Some((*amount, *slot)),
+            _ => None,
+        }
+    }
+
+    // value of the pool's vault LP share, in tokens of the vault
+    fn vault_share(&self, vault: &Pubkey, vault_lp: &Pubkey, vault_lp_mint: &Pubkey, now: u64) -> Option<(u64, u64)> {
+        let (AccountState::Vault(vault_account), vault_slot) = self.accounts.get(vault)? else {
+            return None;
+        };
+        let (lp_amount, lp_slot) = self.amount(vault_lp)?;
+        let (lp_supply, supply_slot) = self.amount(vault_lp_mint)?;
+        if lp_supply == 0 {
+            return Some((0, *vault_slot));
+        }
+        let amount = lp_amount as u128 * vault_account.unlocked_amount(now) as u128 / lp_supply as u128;
+        Some((amount as u64, (*vault_slot).max(lp_slot).max(supply_slot)))
+    }
+
+    fn state(&self, pool: &Pubkey) -> Option<PoolState> {
+        let watched = self.pools.get(pool)?;
+        let (AccountState::Pool(pool_account), pool_slot) = self.accounts.get(pool)? else {
+            return None;
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let (token_a_amount, a_slot) =
+            self.vault_share(&watched.a_vault, &watched.a_vault_lp, &watched.a_vault_lp_mint, now)?;
+        let (token_b_amount, b_slot) =
+            self.vault_share(&watched.b_vault, &watched.b_vault_lp, &watched.b_vault_lp_mint, now)?;
+
+        // constant product invariant per pool LP token
+        let virtual_price = self
+            .amount(&pool_account.lp_mint)
+            .filter(|(lp_supply, _)| *lp_supply > 0)
+            .map(|(lp_supply, _)| (token_a_amount as f64 * token_b_amount as f64).sqrt() / lp_supply as f64);
+
+        Some(PoolState {
+            token_a_mint: pool_account.token_a_mint,
+            token_b_mint: pool_account.token_b_mint,
+            token_a_amount,
+            token_b_amount,
+            virtual_price,
+            trade_fee_bps: pool_account.trade_fee_bps(),
+            enabled: pool_account.enabled,
+            slot: (*pool_slot).max(a_slot).max(b_slot),
+        })
+    }
+}
+
+// Live state of the dynamic AMM pools we hold or are arming, fed by Geyser account
+// updates. Watching or unwatching a pool updates the account subscription.
+pub struct PoolStateCache {
+    inner: Mutex<Inner>,
+    subscriptions: watch::Sender<Vec<String>>,
+}
+
+impl PoolStateCache {
+    pub fn shared() -> SharedPoolStateCache {
+        Arc::new(PoolStateCache {
+            inner: Mutex::new(Inner::default()),
+            subscriptions: watch::Sender::new(Vec::new()),
+        })
+    }
+
+    // One-off RPC snapshot of a pool that is not watched, with its current reserves.
+    pub async fn fetch(rpc: &RpcClient, pool: Pubkey) -> anyhow::Result<AccountsForBuy> {
+        let pool_data = rpc.get_account_data(&pool).await.context("cannot fetch pool")?;
+        let Some(AccountState::Pool(pool_account)) = decode_account(AccountKind::Pool, &pool_data) else {
+            bail!("{} is not a dynamic AMM pool", pool);
+        };
+
+        let vaults = rpc.get_multiple_accounts(&[pool_account.a_vault, pool_account.b_vault]).await?;
+        let vault = |index: usize| match vaults[index]
+            .as_ref()
+            .and_then(|account| decode_account(AccountKind::Vault, &account.data))
+        {
+            Some(AccountState::Vault(vault_account)) => Ok(vault_account),
+            _ => Err(anyhow::anyhow!("cannot decode vault of pool {}", pool)),
+        };
+        let (a_vault, b_vault) = (vault(0)?, vault(1)?);
+
+        let (token_mint, quote_side) = if pool_account.token_b_mint == WSOL_MINT {
+            (pool_account.token_a_mint, QuoteSide::B)
+        } else if pool_account.token_a_mint == WSOL_MINT {
+            (pool_account.token_b_mint, QuoteSide::A)
+        } else {
+            bail!("pool {} has no WSOL side", pool);
+        };
+
+        let mut accounts_for_buy = AccountsForBuy {
+            pool,
+            token_mint,
+            quote_side,
+            a_vault: pool_account.a_vault,
+            b_vault: pool_account.b_vault,
+            a_token_vault: a_vault.token_vault,
+            b_token_vault: b_vault.token_vault,
+            a_vault_lp_mint: a_vault.lp_mint,
+            b_vault_lp_mint: b_vault.lp_mint,
+            a_vault_lp: pool_account.a_vault_lp,
+            b_vault_lp: pool_account.b_vault_lp,
+            protocol_token_a_fee: pool_account.protocol_token_a_fee,
+            protocol_token_b_fee: pool_account.protocol_token_b_fee,
+            token_a_amount: 0,
+            token_b_amount: 0,
+            trade_fee_bps: Some(pool_account.trade_fee_bps()),
+        };
+
+        // same derivation as the live cache, fed from a single consistent read
+        let cache = PoolStateCache::shared();
+        cache.watch(&accounts_for_buy);
+        let accounts: Vec<Pubkey> = cache.inner.lock().unwrap().kinds.keys().copied().collect();
+        let response = rpc.get_multiple_accounts_with_commitment(&accounts, CommitmentConfig::processed()).await?;
+        for (pubkey, account) in accounts.iter().zip(response.value) {
+            if let Some(account) = account {
+                cache.account_handler(*pubkey, &account.data, response.context.slot);
+            }
+        }
+
+        let state = cache.state(&pool).context("missing pool accounts")?;
+        accounts_for_buy.token_a_amount = state.token_a_amount;
+        accounts_for_buy.token_b_amount = state.token_b_amount;
+        Ok(accounts_for_buy)
+    }
+
+    // accounts to subscribe to, updated on every change
+    pub fn subscriptions(&self) -> watch::Receiver<Vec<String>> {
+        self.subscriptions.subscribe()
+    }
+
+    pub fn watch(&self, accounts_for_buy: &AccountsForBuy) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pools.insert(accounts_for_buy.pool, WatchedPool {
+            token_mint: accounts_for_buy.token_mint,
+            a_vault: accounts_for_buy.a_vault,
+            b_vault: accounts_for_buy.b_vault,
+            a_vault_lp: accounts_for_buy.a_vault_lp,
+            b_vault_lp: accounts_for_buy.b_vault_lp,
+            a_vault_lp_mint: accounts_for_buy.a_vault_lp_mint,
+            b_vault_lp_mint: accounts_for_buy.b_vault_lp_mint,
+        });
+        self.update_subscriptions(&mut inner);
+    }
+
+    pub fn unwatch(&self, pool: &Pubkey) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.pools.remove(pool).is_some() {
+            self.update_subscriptions(&mut inner);
+        }
+    }
+
+    // unwatches the pools `keep(pool, token mint)` rejects
+    pub fn retain(&self, keep: impl Fn(&Pubkey, &Pubkey) -> bool) {
+        let mut inner = self.inner.lock().unwrap();
+        let watched = inner.pools.len();
+        inner.pools.retain(|pool, watched| keep(pool, &watched.token_mint));
+        if inner.pools.len() != watched {
+            self.update_subscriptions(&mut inner);
+        }
+    }
+
+    // None until every account of the pool has been received
+    pub fn state(&self, pool: &Pubkey) -> Option<PoolState> {
+        self.inner.lock().unwrap().state(pool)
+    }
+
+    fn update_subscriptions(&self, inner: &mut Inner) {
+        inner.rebuild();
+        let mut accounts: Vec<String> = inner.kinds.keys().map(|account| account.to_string()).collect();
+        accounts.sort();
+        self.subscriptions.send_if_modified(|current| {
+            if *current == accounts {
+                return false;
+            }
+            *current = accounts;
+            true
+        });
+    }
+}
+
+impl AccountHandler for PoolStateCache {
+    fn account_handler(&self, pubkey: Pubkey, data: &[u8], slot: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(kind) = inner.kinds.get(&pubkey).copied() else {
+            return;
+        };
+        // keep the newest state when updates for an account arrive out of order
+        if inner.accounts.get(&pubkey).is_some_and(|(_, last_slot)| *last_slot > slot) {
+            return;
+        }
+        let Some(state) = decode_account(kind, data) else {
+            return;
+        };
+
+        // the pool LP mint is only known once the pool account arrives
+        let new_lp_mint = match &state {
+            AccountState::Pool(pool_account) => !inner.kinds.contains_key(&pool_account.lp_mint),
+            _ => false,
+        };
+        inner.accounts.insert(pubkey, (state, slot));
+        if new_lp_mint {
+            self.update_subscriptions(&mut inner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts_for_buy() -> AccountsForBuy {
+        crate::meteora::tests::accounts_for_buy(QuoteSide::B, 0, 0)
+    }
+
+    fn pool_account(accounts_for_buy: &AccountsForBuy, lp_mint: Pubkey, enabled: bool) -> Vec<u8> {
+        let mut data = vec![0u8; IX_DISCRIMINATOR_SIZE];
+        for pubkey in [
+            lp_mint,
+            accounts_for_buy.token_mint,
+            WSOL_MINT,
+            accounts_for_buy.a_vault,
+            accounts_for_buy.b_vault,
+            accounts_for_buy.a_vault_lp,
+            accounts_for_buy.b_vault_lp,
+        ] {
+            data.extend_from_slice(pubkey.as_ref());
+        }
+        data.extend_from_slice(&[0, enabled as u8]);
+        data.extend_from_slice(accounts_for_buy.protocol_token_a_fee.as_ref());
+        data.extend_from_slice(accounts_for_buy.protocol_token_b_fee.as_ref());
+        data.extend_from_slice(&[0u8; 8 + 24]);
+        // 0.25%
+        data.extend_from_slice(&25u64.to_le_bytes());
+        data.extend_from_slice(&10_000u64.to_le_bytes());
+        data
+    }
+
+    // no locked profit left unless `last_report` is recent
+    fn vault_account(total_amount: u64, locked_profit: u64, last_report: u64) -> Vec<u8> {
+        let mut data = vec![0u8; IX_DISCRIMINATOR_SIZE + 3];
+        data.extend_from_slice(&total_amount.to_le_bytes());
+        data.extend_from_slice(&[0u8; 32 * 37]);
+        data.extend_from_slice(&locked_profit.to_le_bytes());
+        data.extend_from_slice(&last_report.to_le_bytes());
+        // fully unlocked after 1000 seconds
+        data.extend_from_slice(&1_000_000_000u64.to_le_bytes());
+        data
+    }
+
+    fn token_account(amount: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 165];
+        data[TOKEN_ACCOUNT_AMOUNT_OFFSET..TOKEN_ACCOUNT_AMOUNT_OFFSET + 8].copy_from_slice(&amount.to_le_bytes());
+        data
+    }
+
+    fn mint(supply: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 82];
+        data[MINT_SUPPLY_OFFSET..MINT_SUPPLY_OFFSET + 8].copy_from_slice(&supply.to_le_bytes());
+        data
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn state_from_account_updates() {
+        let cache = PoolStateCache::shared();
+        let accounts_for_buy = accounts_for_buy();
+        let lp_mint = Pubkey::new_unique();
+        cache.watch(&accounts_for_buy);
+        assert_eq!(cache.subscriptions().borrow().len(), 7);
+
+        cache.account_handler(
+            accounts_for_buy.pool,
+            &pool_account(&accounts_for_buy, lp_mint, true),
+            10,
+        );
+        // the pool LP mint is subscribed once the pool account names it
+        assert!(cache.subscriptions().borrow().contains(&lp_mint.to_string()));
+
+        cache.account_handler(accounts_for_buy.a_vault, &vault_account(1_000_000, 0, 0), 10);
+        cache.account_handler(accounts_for_buy.b_vault, &vault_account(4_000_000, 0, 0), 11);
+        // the pool owns half of vault A and a quarter of vault B
+        cache.account_handler(accounts_for_buy.a_vault_lp, &token_account(500), 10);
+        cache.account_handler(accounts_for_buy.b_vault_lp, &token_account(250), 10);
+        cache.account_handler(accounts_for_buy.a_vault_lp_mint, &mint(1_000), 10);
+        assert!(cache.state(&accounts_for_buy.pool).is_none());
+        cache.account_handler(accounts_for_buy.b_vault_lp_mint, &mint(1_000), 12);
+
+        let state = cache.state(&accounts_for_buy.pool).unwrap();
+        assert_eq!((state.token_a_amount, state.token_b_amount), (500_000, 1_000_000));
+        assert_eq!((state.trade_fee_bps, state.enabled, state.slot), (25, true, 12));
+        assert!(state.virtual_price.is_none());
+
+        cache.account_handler(lp_mint, &mint(1_000), 12);
+        let virtual_price = cache.state(&accounts_for_buy.pool).unwrap().virtual_price.unwrap();
+        assert!((virtual_price - (500_000f64 * 1_000_000f64).sqrt() / 1_000.0).abs() < 1e-9);
+
+        let expected_out = quote_exact_in(10_000, 1_000_000, 500_000, 25);
+        assert_eq!(state.quote_exact_in(&WSOL_MINT, 10_000), Some(expected_out));
+        assert_eq!(state.quote_exact_in(&Pubkey::new_unique(), 10_000), None);
+    }
+
+    #[test]
+    fn older_update_is_ignored() {
+        let cache = PoolStateCache::shared();
+        let accounts_for_buy = accounts_for_buy();
+        cache.watch(&accounts_for_buy);
+        cache.account_handler(accounts_for_buy.a_vault_lp, &token_account(7), 20);
+        cache.account_handler(accounts_for_buy.a_vault_lp, &token_account(3), 19);
+        let inner = cache.inner.lock().unwrap();
+        assert_eq!(inner.amount(&accounts_for_buy.a_vault_lp), Some((7, 20)));
+    }
+
+    #[test]
+    fn locked_profit_unlocks_linearly() {
+        let data = vault_account(1_000, 100, now() - 500);
+        let Some(AccountState::Vault(vault)) = decode_account(AccountKind::Vault, &data) else {
+            panic!("vault not decoded");
+        };
+        // half of the profit is still locked after 500 of 1000 seconds
+        let unlocked = vault.unlocked_amount(now());
+        assert!((949..=951).contains(&unlocked), "{}", unlocked);
+        assert_eq!(vault.unlocked_amount(now() + 1_000), 1_000);
+    }
+
+    #[test]
+    fn short_account_is_not_decoded() {
+        assert!(decode_account(AccountKind::TokenAccount, &[0u8; 40]).is_none());
+        assert!(decode_account(AccountKind::Pool, &[0u8; 100]).is_none());
+    }
+
+    #[test]
+    fn retain_drops_subscriptions() {
+        let cache = PoolStateCache::shared();
+        let held = accounts_for_buy();
+        let closed = accounts_for_buy();
+        cache.watch(&held);
+        cache.watch(&closed);
+        assert_eq!(cache.subscriptions().borrow().len(), 14);
+
+        cache.retain(|_, mint| *mint == held.token_mint);
+        let subscriptions = cache.subscriptions().borrow().clone();
+        assert_eq!(subscriptions.len(), 7);
+        assert!(subscriptions.contains(&held.pool.to_string()));
+        assert!(!subscriptions.contains(&closed.pool.to_string()));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::config::WaitForLiquidityConfig;
+use crate::meteora::{AccountsForBuy, QuoteSide};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone)]
+pub struct PendingSnipe {
+    pub accounts_for_buy: AccountsForBuy,
+    pub creator: Pubkey,
+    pub armed_slot: u64,
+}
+
+// Pools created without enough liquidity, waiting for their first real deposit.
+pub struct LiquidityWatcher {
+    // lamports
+    min_liquidity: u64,
+    max_slots: u64,
+    pending: HashMap<Pubkey, PendingSnipe>,
+}
+
+impl LiquidityWatcher {
+    // None when the mode is disabled
+    pub fn new(config: &WaitForLiquidityConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        Some(LiquidityWatcher {
+            min_liquidity: (config.min_liquidity_sol * LAMPORTS_PER_SOL as f64) as u64,
+            max_slots: config.max_slots,
+            pending: HashMap::new(),
+        })
+    }
+
+    pub fn should_wait(&self, accounts_for_buy: &AccountsForBuy) -> bool {
+        let (quote_reserve, token_reserve) = accounts_for_buy.reserves();
+        quote_reserve == 0 || token_reserve == 0 || quote_reserve < self.min_liquidity
+    }
+
+    pub fn arm(&mut self, accounts_for_buy: AccountsForBuy, creator: Pubkey, slot: u64) {
+        self.pending.insert(accounts_for_buy.pool, PendingSnipe {
+            accounts_for_buy,
+            creator,
+            armed_slot: slot,
+        });
+    }
+
+    pub fn is_armed(&self, pool: &Pubkey) -> bool {
+        self.pending.contains_key(pool)
+    }
+
+    // drops and returns the pools that waited longer than max_slots
+    pub fn expire(&mut self, slot: u64) -> Vec<Pubkey> {
+        let max_slots = self.max_slots;
+        let expired: Vec<Pubkey> = self
+            .pending
+            .values()
+            .filter(|pending| slot.saturating_sub(pending.armed_slot) > max_slots)
+            .map(|pending| pending.accounts_for_buy.pool)
+            .collect();
+
+        for pool in &expired {
+            self.pending.remove(pool);
+        }
+        expired
+    }
+
+    // Adds the deposit to the armed pool's reserves, and disarms and returns it once a
+    // single deposit brings at least the configured amount of SOL.
+    pub fn on_liquidity_added(
+        &mut self,
+        pool: &Pubkey,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) -> Option<PendingSnipe> {
+        let pending = self.pending.get_mut(pool)?;
+        pending.accounts_for_buy.token_a_amount += token_a_amount;
+        pending.accounts_for_buy.token_b_amount += token_b_amount;
+        let accounts_for_buy = pending.accounts_for_buy;
+
+        let quote_added = match accounts_for_buy.quote_side {
+            QuoteSide::A => token_a_amount,
+            QuoteSide::B => token_b_amount,
+        };
+        if quote_added < self.min_liquidity || self.should_wait(&accounts_for_buy) {
+            return None;
+        }
+        self.pending.remove(pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meteora::tests::accounts_for_buy;
+
+    fn watcher() -> LiquidityWatcher {
+        LiquidityWatcher::new(&WaitForLiquidityConfig {
+            enabled: true,
+            min_liquidity_sol: 1.0,
+            max_slots: 10,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(LiquidityWatcher::new(&WaitForLiquidityConfig::default()).is_none());
+    }
+
+    #[test]
+    fn waits_for_enough_quote_liquidity() {
+        let watcher = watcher();
+        assert!(watcher.should_wait(&accounts_for_buy(QuoteSide::B, 1_000_000, 0)));
+        assert!(watcher.should_wait(&accounts_for_buy(QuoteSide::B, 0, LAMPORTS_PER_SOL)));
+        assert!(watcher.should_wait(&accounts_for_buy(QuoteSide::B, 1_000_000, LAMPORTS_PER_SOL / 2)));
+        assert!(!watcher.should_wait(&accounts_for_buy(QuoteSide::B, 1_000_000, LAMPORTS_PER_SOL)));
+        assert!(!watcher.should_wait(&accounts_for_buy(QuoteSide::A, LAMPORTS_PER_SOL, 1_000_000)));
+    }
+
+    #[test]
+    fn armed_pool_fires_on_a_large_enough_deposit() {
+        let mut watcher = watcher();
+        let accounts_for_buy = accounts_for_buy(QuoteSide::B, 0, 0);
+        let pool = accounts_for_buy.pool;
+        let creator = Pubkey::new_unique();
+        watcher.arm(accounts_for_buy, creator, 100);
+        assert!(watcher.is_armed(&pool));
+
+        // a deposit of another pool, then too small a deposit
+        assert!(watcher.on_liquidity_added(&Pubkey::new_unique(), 1_000_000, LAMPORTS_PER_SOL).is_none());
+        assert!(watcher.on_liquidity_added(&pool, 1_000_000, LAMPORTS_PER_SOL / 2).is_none());
+        assert!(watcher.is_armed(&pool));
+
+        let pending = watcher.on_liquidity_added(&pool, 1_000_000, LAMPORTS_PER_SOL).unwrap();
+        assert!(!watcher.is_armed(&pool));
+        assert_eq!(pending.creator, creator);
+        assert_eq!(pending.armed_slot, 100);
+        // reserves add up every deposit seen while armed
+        assert_eq!(
+            pending.accounts_for_buy.reserves(),
+            (LAMPORTS_PER_SOL * 3 / 2, 2_000_000)
+        );
+    }
+
+    #[test]
+    fn deposits_do_not_add_up_to_the_minimum() {
+        let mut watcher = watcher();
+        let accounts_for_buy = accounts_for_buy(QuoteSide::A, 0, 0);
+        watcher.arm(accounts_for_buy, Pubkey::new_unique(), 0);
+        assert!(watcher.on_liquidity_added(&accounts_for_buy.pool, LAMPORTS_PER_SOL / 2, 1_000).is_none());
+        assert!(watcher.on_liquidity_added(&accounts_for_buy.pool, LAMPORTS_PER_SOL / 2, 1_000).is_none());
+        assert!(watcher.is_armed(&accounts_for_buy.pool));
+    }
+
+    #[test]
+    fn armed_pool_expires_after_max_slots() {
+        let mut watcher = watcher();
+        let early = accounts_for_buy(QuoteSide::B, 0, 0);
+        let late = accounts_for_buy(QuoteSide::B, 0, 0);
+        watcher.arm(early, Pubkey::new_unique(), 100);
+        watcher.arm(late, Pubkey::new_unique(), 105);
+
+        assert!(watcher.expire(110).is_empty());
+        assert_eq!(watcher.expire(111), vec![early.pool]);
+        assert!(!watcher.is_armed(&early.pool));
+        assert!(watcher.is_armed(&late.pool));
+        assert_eq!(watcher.expire(200), vec![late.pool]);
+    }
+}
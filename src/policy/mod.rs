@@ -0,0 +1,445 @@
+use crate::config::PolicyConfig;
+use crate::geyser::TransactionHandler;
+use async_trait::async_trait;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::{TransactionStatusMeta, TransactionTokenBalance};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tracing::info;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+pub type SharedSnipePolicy = Arc<Mutex<SnipePolicy>>;
+pub type SharedHoldings = Arc<Mutex<Holdings>>;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PolicyRejection {
+    #[error("max buys per session reached ({0})")]
+    MaxBuys(u32),
+    #[error("max open positions reached ({0})")]
+    MaxOpenPositions(u32),
+    #[error("mint {0} already sniped")]
+    DuplicateMint(Pubkey),
+    #[error("creator {0} already sniped")]
+    DuplicateCreator(Pubkey),
+    #[error("cooldown, {0:?} left")]
+    Cooldown(Duration),
+    #[error("daily budget exhausted, {0} of {1} lamports spent")]
+    DailyBudget(u64, u64),
+}
+
+// Decides whether a detected pool may be sniped. Shared by every controller so
+// limits hold across pool programs.
+#[derive(Debug)]
+pub struct SnipePolicy {
+    config: PolicyConfig,
+    buy_amount: u64,
+    daily_budget: Option<u64>,
+    buys: u32,
+    // mint -> lamports charged to the budget
+    open_positions: HashMap<Pubkey, u64>,
+    seen_mints: HashSet<Pubkey>,
+    seen_creators: HashSet<Pubkey>,
+    last_snipe: Option<Instant>,
+    budget_day: u64,
+    spent_today: u64,
+}
+
+impl SnipePolicy {
+    // buy_amount: lamports a buy can spend at most, see Bench::max_buy_amount
+    pub fn new(config: PolicyConfig, buy_amount: u64) -> Self {
+        let daily_budget = config.daily_budget_sol.map(|sol| (sol * LAMPORTS_PER_SOL as f64) as u64);
+        SnipePolicy {
+            config,
+            buy_amount,
+            daily_budget,
+            buys: 0,
+            open_positions: HashMap::new(),
+            seen_mints: HashSet::new(),
+            seen_creators: HashSet::new(),
+            last_snipe: None,
+            budget_day: current_day(),
+            spent_today: 0,
+        }
+    }
+
+    pub fn shared(config: PolicyConfig, buy_amount: u64) -> SharedSnipePolicy {
+        Arc::new(Mutex::new(Self::new(config, buy_amount)))
+    }
+
+    // Checks every limit and, when the snipe is allowed, records it as an open position.
+    pub fn try_acquire(&mut self, mint: Pubkey, creator: Pubkey) -> Result<(), PolicyRejection> {
+        self.acquire(mint, Some(creator), self.buy_amount)
+    }
+
+    // A mirrored buy has no pool creator to dedupe on and spends amount, not buy_amount.
+    pub fn try_acquire_mirror(&mut self, mint: Pubkey, amount: u64) -> Result<(), PolicyRejection> {
+        self.acquire(mint, None, amount)
+    }
+
+    fn acquire(&mut self, mint: Pubkey, creator: Option<Pubkey>, amount: u64) -> Result<(), PolicyRejection> {
+        if let Some(max_buys) = self.config.max_buys {
+            if self.buys >= max_buys {
+                return Err(PolicyRejection::MaxBuys(max_buys));
+            }
+        }
+
+        if let Some(max_open_positions) = self.config.max_open_positions {
+            if self.open_positions.len() as u32 >= max_open_positions {
+                return Err(PolicyRejection::MaxOpenPositions(max_open_positions));
+            }
+        }
+
+        if self.config.dedupe_mint && self.seen_mints.contains(&mint) {
+            return Err(PolicyRejection::DuplicateMint(mint));
+        }
+
+        if let Some(creator) =
+            creator.filter(|creator| self.config.dedupe_creator && self.seen_creators.contains(creator))
+        {
+            return Err(PolicyRejection::DuplicateCreator(creator));
+        }
+
+        let cooldown = Duration::from_millis(self.config.cooldown_ms);
+        if let Some(elapsed) = self.last_snipe.map(|last_snipe| last_snipe.elapsed()) {
+            if elapsed < cooldown {
+                return Err(PolicyRejection::Cooldown(cooldown - elapsed));
+            }
+        }
+
+        let today = current_day();
+        if today != self.budget_day {
+            self.budget_day = today;
+            self.spent_today = 0;
+        }
+        if let Some(daily_budget) = self.daily_budget {
+            if self.spent_today + amount > daily_budget {
+                return Err(PolicyRejection::DailyBudget(self.spent_today, daily_budget));
+            }
+        }
+
+        self.buys += 1;
+        self.open_positions.insert(mint, amount);
+        self.seen_mints.insert(mint);
+        self.seen_creators.extend(creator);
+        self.last_snipe = Some(Instant::now());
+        self.spent_today += amount;
+        Ok(())
+    }
+
+    // the position was sold or closed, its slot is free again
+    pub fn release(&mut self, mint: &Pubkey) {
+        if self.open_positions.remove(mint).is_some() {
+            info!("position in {} released", mint);
+        }
+    }
+
+    // the buy did not land: the slot is free and nothing was spent
+    pub fn cancel(&mut self, mint: &Pubkey) {
+        if let Some(spent) = self.open_positions.remove(mint) {
+            self.spent_today = self.spent_today.saturating_sub(spent);
+            info!("position in {} cancelled, the buy did not land", mint);
+        }
+    }
+
+    pub fn is_open(&self, mint: &Pubkey) -> bool {
+        self.open_positions.contains_key(mint)
+    }
+}
+
+// Releases a position once one of our wallets holds none of its token, whatever sold it:
+// a mirrored swap, the sell command or a manual trade. With split buys the first wallet
+// to sell out frees the slot.
+// Our token balances as the stream sees them, tokens held before the session are unknown.
+#[derive(Default)]
+pub struct Holdings {
+    // (owner, mint) -> balance
+    balances: HashMap<(Pubkey, Pubkey), u64>,
+}
+
+impl Holdings {
+    pub fn shared() -> SharedHoldings {
+        Arc::new(Mutex::new(Holdings::default()))
+    }
+
+    pub fn balance(&self, owner: &Pubkey, mint: &Pubkey) -> u64 {
+        self.balances.get(&(*owner, *mint)).copied().unwrap_or_default()
+    }
+
+    fn set(&mut self, owner: Pubkey, mint: Pubkey, amount: u64) {
+        if amount == 0 {
+            self.balances.remove(&(owner, mint));
+        } else {
+            self.balances.insert((owner, mint), amount);
+        }
+    }
+}
+
+pub struct PositionTracker {
+    policy: SharedSnipePolicy,
+    holdings: SharedHoldings,
+    wallets: HashSet<Pubkey>,
+}
+
+impl PositionTracker {
+    pub fn new(policy: SharedSnipePolicy, holdings: SharedHoldings, wallets: HashSet<Pubkey>) -> Self {
+        PositionTracker {
+            policy,
+            holdings,
+            wallets,
+        }
+    }
+
+    // (owner, mint) -> balance, for our wallets only
+    fn balances(&self, token_balances: &Option<Vec<TransactionTokenBalance>>) -> HashMap<(Pubkey, Pubkey), u64> {
+        let mut balances = HashMap::new();
+        for balance in token_balances.iter().flatten() {
+            let (Ok(owner), Ok(mint)) = (Pubkey::from_str(&balance.owner), Pubkey::from_str(&balance.mint)) else {
+                continue;
+            };
+            if self.wallets.contains(&owner) {
+                let amount: u64 = balance.ui_token_amount.amount.parse().unwrap_or_default();
+                *balances.entry((owner, mint)).or_default() += amount;
+            }
+        }
+        balances
+    }
+}
+
+#[async_trait]
+impl TransactionHandler for PositionTracker {
+    async fn transaction_handler(
+        &mut self,
+        _signature: Signature,
+        _transaction: VersionedTransaction,
+        meta: TransactionStatusMeta,
+        _is_vote: bool,
+        _slot: u64,
+    ) -> anyhow::Result<()> {
+        let pre = self.balances(&meta.pre_token_balances);
+        // a new token account has no pre balance, a closed one no post balance
+        let post = self.balances(&meta.post_token_balances);
+        if pre.is_empty() && post.is_empty() {
+            return Ok(());
+        }
+        {
+            let mut holdings = self.holdings.lock().unwrap();
+            for &(owner, mint) in pre.keys().chain(post.keys()) {
+                holdings.set(owner, mint, post.get(&(owner, mint)).copied().unwrap_or_default());
+            }
+        }
+        let mut policy = self.policy.lock().unwrap();
+        for ((owner, mint), amount) in pre {
+            if amount > 0 && post.get(&(owner, mint)).copied().unwrap_or_default() == 0 && policy.is_open(&mint) {
+                policy.release(&mint);
+            }
+        }
+        Ok(())
+    }
+}
+
+// UTC day number, the daily budget resets when it changes
+fn current_day() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / SECONDS_PER_DAY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUY_AMOUNT: u64 = LAMPORTS_PER_SOL / 10;
+
+    fn unlimited() -> PolicyConfig {
+        PolicyConfig {
+            max_buys: None,
+            ..PolicyConfig::default()
+        }
+    }
+
+    #[test]
+    fn default_allows_a_single_buy() {
+        let mut policy = SnipePolicy::new(PolicyConfig::default(), BUY_AMOUNT);
+        assert_eq!(policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()), Ok(()));
+        assert_eq!(
+            policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()),
+            Err(PolicyRejection::MaxBuys(1))
+        );
+    }
+
+    #[test]
+    fn cancel_frees_the_slot_and_the_budget_but_counts_the_buy() {
+        let config = PolicyConfig {
+            max_buys: Some(2),
+            max_open_positions: Some(1),
+            daily_budget_sol: Some(0.1),
+            ..PolicyConfig::default()
+        };
+        let mut policy = SnipePolicy::new(config, BUY_AMOUNT);
+        let mint = Pubkey::new_unique();
+        assert_eq!(policy.try_acquire(mint, Pubkey::new_unique()), Ok(()));
+        assert!(policy.is_open(&mint));
+        assert_eq!(
+            policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()),
+            Err(PolicyRejection::MaxOpenPositions(1))
+        );
+
+        policy.cancel(&mint);
+        assert!(!policy.is_open(&mint));
+        assert_eq!(policy.spent_today, 0);
+        assert_eq!(policy.buys, 1);
+        // the cancelled mint stays seen
+        assert_eq!(
+            policy.try_acquire(mint, Pubkey::new_unique()),
+            Err(PolicyRejection::DuplicateMint(mint))
+        );
+
+        assert_eq!(policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()), Ok(()));
+        assert_eq!(policy.buys, 2);
+        assert_eq!(
+            policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()),
+            Err(PolicyRejection::MaxBuys(2))
+        );
+    }
+
+    #[test]
+    fn release_keeps_the_spent_budget() {
+        let config = PolicyConfig {
+            daily_budget_sol: Some(0.15),
+            ..unlimited()
+        };
+        let mut policy = SnipePolicy::new(config, BUY_AMOUNT);
+        let mint = Pubkey::new_unique();
+        assert_eq!(policy.try_acquire(mint, Pubkey::new_unique()), Ok(()));
+        policy.release(&mint);
+        assert!(!policy.is_open(&mint));
+        assert_eq!(
+            policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()),
+            Err(PolicyRejection::DailyBudget(BUY_AMOUNT, 150_000_000))
+        );
+    }
+
+    #[test]
+    fn duplicate_creator() {
+        let config = PolicyConfig {
+            dedupe_creator: true,
+            ..unlimited()
+        };
+        let mut policy = SnipePolicy::new(config, BUY_AMOUNT);
+        let creator = Pubkey::new_unique();
+        assert_eq!(policy.try_acquire(Pubkey::new_unique(), creator), Ok(()));
+        assert_eq!(
+            policy.try_acquire(Pubkey::new_unique(), creator),
+            Err(PolicyRejection::DuplicateCreator(creator))
+        );
+        // a mirrored buy has no creator
+        assert_eq!(policy.try_acquire_mirror(Pubkey::new_unique(), BUY_AMOUNT), Ok(()));
+    }
+
+    #[test]
+    fn cooldown_between_snipes() {
+        let config = PolicyConfig {
+            cooldown_ms: 60_000,
+            ..unlimited()
+        };
+        let mut policy = SnipePolicy::new(config, BUY_AMOUNT);
+        assert_eq!(policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()), Ok(()));
+        assert!(matches!(
+            policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()),
+            Err(PolicyRejection::Cooldown(_))
+        ));
+    }
+
+    #[test]
+    fn mirrored_buy_spends_its_own_amount() {
+        let config = PolicyConfig {
+            daily_budget_sol: Some(0.1),
+            ..unlimited()
+        };
+        let mut policy = SnipePolicy::new(config, BUY_AMOUNT);
+        let mint = Pubkey::new_unique();
+        assert_eq!(policy.try_acquire_mirror(mint, BUY_AMOUNT / 4), Ok(()));
+        assert_eq!(policy.spent_today, BUY_AMOUNT / 4);
+        policy.cancel(&mint);
+        assert_eq!(policy.spent_today, 0);
+    }
+
+    #[test]
+    fn budget_resets_on_a_new_day() {
+        let config = PolicyConfig {
+            daily_budget_sol: Some(0.1),
+            ..unlimited()
+        };
+        let mut policy = SnipePolicy::new(config, BUY_AMOUNT);
+        assert_eq!(policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()), Ok(()));
+        assert!(policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()).is_err());
+        policy.budget_day -= 1;
+        assert_eq!(policy.try_acquire(Pubkey::new_unique(), Pubkey::new_unique()), Ok(()));
+    }
+
+    fn token_balance(owner: Pubkey, mint: Pubkey, amount: u64) -> TransactionTokenBalance {
+        TransactionTokenBalance {
+            account_index: 1,
+            mint: mint.to_string(),
+            ui_token_amount: serde_json::from_value(serde_json::json!({
+                "uiAmount": null,
+                "decimals": 6,
+                "amount": amount.to_string(),
+                "uiAmountString": "",
+            }))
+            .unwrap(),
+            owner: owner.to_string(),
+            program_id: String::new(),
+        }
+    }
+
+    async fn handle(
+        tracker: &mut PositionTracker,
+        pre: Vec<TransactionTokenBalance>,
+        post: Vec<TransactionTokenBalance>,
+    ) {
+        let meta = TransactionStatusMeta {
+            pre_token_balances: Some(pre),
+            post_token_balances: Some(post),
+            ..TransactionStatusMeta::default()
+        };
+        tracker
+            .transaction_handler(Signature::default(), VersionedTransaction::default(), meta, false, 1)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn tracker_follows_holdings_and_releases_sold_positions() {
+        let policy = SnipePolicy::shared(unlimited(), BUY_AMOUNT);
+        let holdings = Holdings::shared();
+        let (wallet, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut tracker = PositionTracker::new(policy.clone(), holdings.clone(), HashSet::from([wallet]));
+        assert_eq!(policy.lock().unwrap().try_acquire(mint, Pubkey::new_unique()), Ok(()));
+
+        // the buy opens the token account
+        handle(&mut tracker, vec![], vec![token_balance(wallet, mint, 1_000)]).await;
+        assert_eq!(holdings.lock().unwrap().balance(&wallet, &mint), 1_000);
+        // someone else's balance is not ours
+        let other = Pubkey::new_unique();
+        handle(&mut tracker, vec![], vec![token_balance(other, mint, 500)]).await;
+        assert_eq!(holdings.lock().unwrap().balance(&other, &mint), 0);
+
+        handle(&mut tracker, vec![token_balance(wallet, mint, 1_000)], vec![
+            token_balance(wallet, mint, 400),
+        ])
+        .await;
+        assert_eq!(holdings.lock().unwrap().balance(&wallet, &mint), 400);
+        assert!(policy.lock().unwrap().is_open(&mint));
+
+        // selling the rest closes the token account
+        handle(&mut tracker, vec![token_balance(wallet, mint, 400)], vec![]).await;
+        assert_eq!(holdings.lock().unwrap().balance(&wallet, &mint), 0);
+        assert!(!policy.lock().unwrap().is_open(&mint));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::config::PriorityFeeConfig;
+use crate::core::account_keys;
+use crate::geyser::TransactionHandler;
+use crate::tx_senders::constants::{METEORA_DAMM_V2_PROGRAM, METEORA_DLMM_PROGRAM, METEORA_POOLS_PROGRAM};
+use crate::tx_senders::tip_accounts::TipAccounts;
+use async_trait::async_trait;
+use solana_sdk::compute_budget;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::system_program;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::TransactionStatusMeta;
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// ComputeBudgetInstruction::SetComputeUnitPrice, borsh: tag then u64
+const SET_COMPUTE_UNIT_PRICE_TAG: u8 = 3;
+// SystemInstruction::Transfer, bincode: u32 tag then u64
+const SYSTEM_TRANSFER_TAG: u32 = 2;
+const METEORA_PROGRAMS: [Pubkey; 3] = [METEORA_POOLS_PROGRAM, METEORA_DLMM_PROGRAM, METEORA_DAMM_V2_PROGRAM];
+
+pub type SharedPriorityFeeOracle = Arc<PriorityFeeOracle>;
+
+// what a trade pays: micro-lamports per CU and the tip in lamports
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PriorityFees {
+    pub compute_unit_price: u64,
+    pub tip: u64,
+}
+
+// Rolling (slot, value) samples of one fee and the bounded percentile served to senders.
+struct FeeSeries {
+    percentile: u8,
+    min: u64,
+    max: u64,
+    samples: Mutex<VecDeque<(u64, u64)>>,
+    current: AtomicU64,
+}
+
+impl FeeSeries {
+    fn new(percentile: u8, min: u64, max: u64, fallback: u64) -> Self {
+        FeeSeries {
+            percentile: percentile.min(100),
+            min,
+            max,
+            samples: Mutex::new(VecDeque::new()),
+            current: AtomicU64::new(fallback.max(min).min(max)),
+        }
+    }
+
+    fn record(&self, slot: u64, value: u64) {
+        self.samples.lock().unwrap().push_back((slot, value));
+    }
+
+    // drops the samples before oldest_slot and recomputes the percentile of the rest
+    fn refresh(&self, oldest_slot: u64) {
+        let mut samples = self.samples.lock().unwrap();
+        while samples.front().is_some_and(|(slot, _)| *slot < oldest_slot) {
+            samples.pop_front();
+        }
+        if samples.is_empty() {
+            return;
+        }
+
+        let mut values: Vec<u64> = samples.iter().map(|(_, value)| *value).collect();
+        drop(samples);
+        let index = (values.len() - 1) * self.percentile as usize / 100;
+        let (_, value, _) = values.select_nth_unstable(index);
+        self.current.store((*value).max(self.min).min(self.max), Ordering::Relaxed);
+    }
+
+    fn current(&self) -> u64 {
+        self.current.load(Ordering::Relaxed)
+    }
+}
+
+// Watches the compute budget and tips of the Meteora transactions in the Geyser stream.
+// Percentiles are recomputed once per slot, reading them on the hot path is two loads.
+pub struct PriorityFeeOracle {
+    window_slots: u64,
+    last_slot: AtomicU64,
+    tip_accounts: Arc<TipAccounts>,
+    // our own trades would bid the fees up against themselves
+    wallets: HashSet<Pubkey>,
+    compute_unit_price: FeeSeries,
+    tip: FeeSeries,
+}
+
+impl PriorityFeeOracle {
+    // the static fees, compute_unit_price and tip in lamports, serve until samples come in
+    pub fn new(
+        config: &PriorityFeeConfig,
+        fallback: PriorityFees,
+        tip_accounts: Arc<TipAccounts>,
+        wallets: HashSet<Pubkey>,
+    ) -> Self {
+        let to_lamports = |sol: f64| (sol * LAMPORTS_PER_SOL as f64) as u64;
+        PriorityFeeOracle {
+            window_slots: config.window_slots,
+            last_slot: AtomicU64::new(0),
+            tip_accounts,
+            wallets,
+            compute_unit_price: FeeSeries::new(
+                config.compute_unit_price_percentile,
+                config.min_compute_unit_price,
+                config.max_compute_unit_price,
+                fallback.compute_unit_price,
+            ),
+            tip: FeeSeries::new(
+                config.tip_percentile,
+                to_lamports(config.min_tip),
+                to_lamports(config.max_tip),
+                fallback.tip,
+            ),
+        }
+    }
+
+    pub fn shared(
+        config: &PriorityFeeConfig,
+        fallback: PriorityFees,
+        tip_accounts: Arc<TipAccounts>,
+        wallets: HashSet<Pubkey>,
+    ) -> SharedPriorityFeeOracle {
+        Arc::new(Self::new(config, fallback, tip_accounts, wallets))
+    }
+
+    pub fn fees(&self) -> PriorityFees {
+        PriorityFees {
+            compute_unit_price: self.compute_unit_price.current(),
+            tip: self.tip.current(),
+        }
+    }
+
+    // the stream also carries our wallets and the copy-traded ones, whatever they trade
+    fn samples(&self, transaction: &VersionedTransaction, meta: &TransactionStatusMeta) -> bool {
+        let message = &transaction.message;
+        let signers = &message.static_account_keys()[..message.header().num_required_signatures as usize];
+        if signers.iter().any(|signer| self.wallets.contains(signer)) {
+            return false;
+        }
+        account_keys(meta, transaction).iter().any(|key| METEORA_PROGRAMS.contains(key))
+    }
+
+    fn observe(&self, transaction: &VersionedTransaction, slot: u64) {
+        // transactions without a compute budget pay no priority fee, they count as 0
+        self.compute_unit_price.record(slot, compute_unit_price(transaction).unwrap_or(0));
+        // only tipping transactions tell what a tip should be
+        let tip = tip(transaction, &self.tip_accounts);
+        if tip > 0 {
+            self.tip.record(slot, tip);
+        }
+
+        if self.last_slot.fetch_max(slot, Ordering::Relaxed) < slot {
+            let oldest_slot = slot.saturating_sub(self.window_slots);
+            self.compute_unit_price.refresh(oldest_slot);
+            self.tip.refresh(oldest_slot);
+        }
+    }
+}
+
+fn compute_unit_price(transaction: &VersionedTransaction) -> Option<u64> {
+    let account_keys = transaction.message.static_account_keys();
+    transaction.message.instructions().iter().find_map(|instruction| {
+        let program_id = account_keys.get(instruction.program_id_index as usize)?;
+        if *program_id != compute_budget::id() || instruction.data.first() != Some(&SET_COMPUTE_UNIT_PRICE_TAG) {
+            return None;
+        }
+        Some(u64::from_le_bytes(instruction.data.get(1..9)?.try_into().ok()?))
+    })
+}
+
+// lamports transferred to block engine tip accounts, static keys only as looked up tips do not count
+fn tip(transaction: &VersionedTransaction, tip_accounts: &TipAccounts) -> u64 {
+    let account_keys = transaction.message.static_account_keys();
+    let key = |index: &u8| account_keys.get(*index as usize);
+
+    transaction
+        .message
+        .instructions()
+        .iter()
+        .filter_map(|instruction| {
+            if key(&instruction.program_id_index) != Some(&system_program::id()) {
+                return None;
+            }
+            let tag = u32::from_le_bytes(instruction.data.get(0..4)?.try_into().ok()?);
+            if tag != SYSTEM_TRANSFER_TAG || !tip_accounts.contains(key(instruction.accounts.get(1)?)?) {
+                return None;
+            }
+            Some(u64::from_le_bytes(instruction.data.get(4..12)?.try_into().ok()?))
+        })
+        .sum()
+}
+
+#[async_trait]
+impl TransactionHandler for SharedPriorityFeeOracle {
+    async fn transaction_handler(
+        &mut self,
+        _signature: Signature,
+        transaction: VersionedTransaction,
+        meta: TransactionStatusMeta,
+        _is_vote: bool,
+        slot: u64,
+    ) -> anyhow::Result<()> {
+        if self.samples(&transaction, &meta) {
+            self.observe(&transaction, slot);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TipAccountsConfig;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::message::Message;
+    use solana_sdk::transaction::Transaction;
+
+    fn oracle(wallets: HashSet<Pubkey>) -> PriorityFeeOracle {
+        let config = PriorityFeeConfig {
+            enabled: true,
+            window_slots: 10,
+            compute_unit_price_percentile: 50,
+            min_compute_unit_price: 100,
+            max_compute_unit_price: 10_000,
+            ..PriorityFeeConfig::default()
+        };
+        let fallback = PriorityFees {
+            compute_unit_price: 1,
+            tip: 0,
+        };
+        PriorityFeeOracle::new(
+            &config,
+            fallback,
+            Arc::new(TipAccounts::new(&TipAccountsConfig::default())),
+            wallets,
+        )
+    }
+
+    fn transaction(signer: Pubkey, program: Pubkey, compute_unit_price: u64) -> VersionedTransaction {
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_price(compute_unit_price),
+            Instruction::new_with_bytes(program, &[], vec![]),
+        ];
+        Transaction::new_unsigned(Message::new(&instructions, Some(&signer))).into()
+    }
+
+    #[test]
+    fn percentile_of_the_samples() {
+        let series = FeeSeries::new(50, 0, u64::MAX, 0);
+        for value in [500, 100, 400, 200, 300] {
+            series.record(1, value);
+        }
+        series.refresh(0);
+        assert_eq!(series.current(), 300);
+
+        let series = FeeSeries::new(90, 0, u64::MAX, 0);
+        for value in 1..=10 {
+            series.record(1, value);
+        }
+        series.refresh(0);
+        assert_eq!(series.current(), 9);
+    }
+
+    #[test]
+    fn values_are_clamped() {
+        let series = FeeSeries::new(50, 100, 1_000, 5_000);
+        // the fallback too
+        assert_eq!(series.current(), 1_000);
+        series.record(1, 10);
+        series.refresh(0);
+        assert_eq!(series.current(), 100);
+        series.record(2, 50_000);
+        series.record(2, 50_000);
+        series.refresh(2);
+        assert_eq!(series.current(), 1_000);
+    }
+
+    #[test]
+    fn old_samples_leave_the_window() {
+        let series = FeeSeries::new(100, 0, u64::MAX, 0);
+        series.record(1, 900);
+        series.record(5, 200);
+        series.refresh(2);
+        assert_eq!(series.current(), 200);
+        // an empty window keeps the last value
+        series.refresh(10);
+        assert_eq!(series.current(), 200);
+    }
+
+    #[test]
+    fn own_and_unrelated_transactions_are_not_sampled() {
+        let wallet = Pubkey::new_unique();
+        let oracle = oracle(HashSet::from([wallet]));
+        let meta = TransactionStatusMeta::default();
+
+        assert!(oracle.samples(&transaction(Pubkey::new_unique(), METEORA_DLMM_PROGRAM, 5_000), &meta));
+        assert!(!oracle.samples(&transaction(wallet, METEORA_DLMM_PROGRAM, 5_000), &meta));
+        assert!(!oracle.samples(&transaction(Pubkey::new_unique(), Pubkey::new_unique(), 5_000), &meta));
+    }
+
+    #[test]
+    fn compute_unit_price_is_read() {
+        let transaction = transaction(Pubkey::new_unique(), METEORA_POOLS_PROGRAM, 4_321);
+        assert_eq!(compute_unit_price(&transaction), Some(4_321));
+
+        let oracle = oracle(HashSet::new());
+        oracle.observe(&transaction, 1);
+        oracle.observe(&transaction, 2);
+        assert_eq!(oracle.fees().compute_unit_price, 4_321);
+    }
+}
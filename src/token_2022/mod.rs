@@ -0,0 +1,319 @@
+use crate::core::ExtractedInstruction;
+use crate::meteora::BPS_DENOMINATOR;
+use crate::tx_senders::constants::{TOKEN_2022_PROGRAM, TOKEN_PROGRAM};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::TransactionStatusMeta;
+use std::collections::HashMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+// token instruction tags, the extension ones are followed by a sub-instruction byte
+pub const INITIALIZE_MINT_TAG: u8 = 0;
+pub const INITIALIZE_MINT2_TAG: u8 = 20;
+pub const TRANSFER_FEE_EXTENSION_TAG: u8 = 26;
+pub const TRANSFER_HOOK_EXTENSION_TAG: u8 = 36;
+pub const INITIALIZE_EXTENSION: u8 = 0;
+
+// Token-2022 mint account layout: the base mint padded to the size of a token
+// account, the account type, then the extensions as type/length/value entries
+const ACCOUNT_TYPE_OFFSET: usize = 165;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+const EXTENSION_TRANSFER_HOOK: u16 = 14;
+
+#[derive(Error, Debug)]
+pub enum MintRejection {
+    #[error("mint {0} has a transfer hook")]
+    TransferHook(Pubkey),
+    #[error("mint {0} was not initialized in this transaction, its extensions are unknown")]
+    UnknownExtensions(Pubkey),
+    #[error("mint {0} is owned by unsupported program {1}")]
+    UnsupportedProgram(Pubkey, Pubkey),
+    #[error("mint {0} could not be fetched: {1}")]
+    Unfetched(Pubkey, String),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TransferFee {
+    pub basis_points: u16,
+    pub maximum_fee: u64,
+}
+
+impl TransferFee {
+    // what the recipient gets, the fee is rounded up and capped like the token program does
+    pub fn amount_after_fee(&self, amount: u64) -> u64 {
+        let raw_fee = (amount as u128 * self.basis_points as u128).div_ceil(BPS_DENOMINATOR as u128);
+        amount - (raw_fee as u64).min(self.maximum_fee)
+    }
+
+    // TransferFeeConfig extension: both authorities, the withheld amount, then the
+    // older and newer fees as epoch, maximum fee and basis points
+    fn decode_config(data: &[u8]) -> Option<Self> {
+        let fee_at = |offset: usize| -> Option<Self> {
+            Some(TransferFee {
+                maximum_fee: u64::from_le_bytes(data.get(offset + 8..offset + 16)?.try_into().ok()?),
+                basis_points: u16::from_le_bytes(data.get(offset + 16..offset + 18)?.try_into().ok()?),
+            })
+        };
+        let older = fee_at(72)?;
+        let newer = fee_at(90)?;
+        // the newer fee only applies from its epoch, assume the larger one
+        Some(if newer.basis_points >= older.basis_points {
+            newer
+        } else {
+            older
+        })
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut rest = data;
+        // transfer fee config authority, withdraw withheld authority
+        for _ in 0..2 {
+            rest = match rest.first()? {
+                0 => rest.get(1..)?,
+                1 => rest.get(33..)?,
+                _ => return None,
+            };
+        }
+        Some(TransferFee {
+            basis_points: u16::from_le_bytes(rest.get(..2)?.try_into().ok()?),
+            maximum_fee: u64::from_le_bytes(rest.get(2..10)?.try_into().ok()?),
+        })
+    }
+}
+
+// mint -> owning token program, taken from the token balances the transaction touched
+pub fn token_programs_by_mint(meta: &TransactionStatusMeta) -> HashMap<Pubkey, Pubkey> {
+    meta.post_token_balances
+        .iter()
+        .flatten()
+        .filter_map(|balance| {
+            let mint = Pubkey::from_str(&balance.mint).ok()?;
+            let program = Pubkey::from_str(&balance.program_id).ok()?;
+            Some((mint, program))
+        })
+        .collect()
+}
+
+// Token-2022 extensions can only be known from the mint account, which a geyser
+// transaction update does not carry. Launches create the mint in the same
+// transaction as the pool, so its extension initializations are read from there.
+// Returns the transfer fee of the mint, if any, and UnknownExtensions when the
+// mint was created earlier, fetch_mint then reads the account itself.
+pub fn inspect_mint(
+    mint: Pubkey,
+    token_program: Pubkey,
+    instructions: &[ExtractedInstruction],
+) -> Result<Option<TransferFee>, MintRejection> {
+    if token_program == TOKEN_PROGRAM {
+        return Ok(None);
+    }
+    if token_program != TOKEN_2022_PROGRAM {
+        return Err(MintRejection::UnsupportedProgram(mint, token_program));
+    }
+
+    let mut initialized = false;
+    let mut transfer_fee = None;
+
+    for extracted in instructions {
+        let instruction = &extracted.instruction;
+        if instruction.program_id != TOKEN_2022_PROGRAM
+            || instruction.accounts.first().map(|account| account.pubkey) != Some(mint)
+        {
+            continue;
+        }
+
+        match instruction.data.as_slice() {
+            [INITIALIZE_MINT_TAG | INITIALIZE_MINT2_TAG, ..] => initialized = true,
+            [TRANSFER_HOOK_EXTENSION_TAG, INITIALIZE_EXTENSION, ..] => {
+                return Err(MintRejection::TransferHook(mint));
+            }
+            [TRANSFER_FEE_EXTENSION_TAG, INITIALIZE_EXTENSION, data @ ..] => {
+                transfer_fee = TransferFee::decode(data);
+            }
+            _ => {}
+        }
+    }
+
+    if !initialized {
+        return Err(MintRejection::UnknownExtensions(mint));
+    }
+
+    Ok(transfer_fee)
+}
+
+// For a Token-2022 mint created before the pool, costs an rpc round trip.
+pub async fn fetch_mint(rpc: &RpcClient, mint: Pubkey) -> Result<Option<TransferFee>, MintRejection> {
+    let account = rpc
+        .get_account_with_commitment(&mint, CommitmentConfig::processed())
+        .await
+        .map_err(|err| MintRejection::Unfetched(mint, err.to_string()))?
+        .value
+        .ok_or_else(|| MintRejection::Unfetched(mint, "account not found".to_string()))?;
+    if account.owner != TOKEN_2022_PROGRAM {
+        return Err(MintRejection::UnsupportedProgram(mint, account.owner));
+    }
+    inspect_mint_account(mint, &account.data)
+}
+
+pub fn inspect_mint_account(mint: Pubkey, data: &[u8]) -> Result<Option<TransferFee>, MintRejection> {
+    // a mint without extensions is a plain 82 byte mint
+    if data.len() <= ACCOUNT_TYPE_OFFSET {
+        return Ok(None);
+    }
+    if data[ACCOUNT_TYPE_OFFSET] != ACCOUNT_TYPE_MINT {
+        return Err(MintRejection::Unfetched(mint, "not a mint account".to_string()));
+    }
+
+    let mut transfer_fee = None;
+    let mut rest = &data[ACCOUNT_TYPE_OFFSET + 1..];
+    while let [t0, t1, l0, l1, tail @ ..] = rest {
+        let extension = u16::from_le_bytes([*t0, *t1]);
+        let length = u16::from_le_bytes([*l0, *l1]) as usize;
+        // uninitialized padding ends the list
+        if extension == 0 {
+            break;
+        }
+        let Some(value) = tail.get(..length) else {
+            return Err(MintRejection::Unfetched(mint, "truncated extension".to_string()));
+        };
+        match extension {
+            EXTENSION_TRANSFER_FEE_CONFIG => transfer_fee = TransferFee::decode_config(value),
+            // authority then hook program, a zero program means no hook
+            EXTENSION_TRANSFER_HOOK if value.get(32..64).is_some_and(|program| program.iter().any(|b| *b != 0)) => {
+                return Err(MintRejection::TransferHook(mint));
+            }
+            _ => {}
+        }
+        rest = &tail[length..];
+    }
+    Ok(transfer_fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+
+    // TransferFeeConfig value: two authorities, the withheld amount, then the older and newer fees
+    fn transfer_fee_config(older: (u64, u16), newer: (u64, u16)) -> Vec<u8> {
+        let mut value = vec![0u8; 72];
+        for (maximum_fee, basis_points) in [older, newer] {
+            value.extend_from_slice(&0u64.to_le_bytes());
+            value.extend_from_slice(&maximum_fee.to_le_bytes());
+            value.extend_from_slice(&basis_points.to_le_bytes());
+        }
+        value
+    }
+
+    fn mint_account(extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut data = vec![0u8; ACCOUNT_TYPE_OFFSET];
+        data.push(ACCOUNT_TYPE_MINT);
+        for (extension, value) in extensions {
+            data.extend_from_slice(&extension.to_le_bytes());
+            data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            data.extend_from_slice(value);
+        }
+        data
+    }
+
+    fn token_2022_instruction(mint: Pubkey, data: Vec<u8>) -> ExtractedInstruction {
+        ExtractedInstruction {
+            outer_index: 0,
+            stack_height: 1,
+            instruction: Instruction {
+                program_id: TOKEN_2022_PROGRAM,
+                accounts: vec![AccountMeta::new(mint, false)],
+                data,
+            },
+        }
+    }
+
+    #[test]
+    fn fee_is_rounded_up_and_capped() {
+        let transfer_fee = TransferFee {
+            basis_points: 100,
+            maximum_fee: 5,
+        };
+        assert_eq!(transfer_fee.amount_after_fee(250), 247);
+        assert_eq!(transfer_fee.amount_after_fee(10_000), 9_995);
+    }
+
+    #[test]
+    fn plain_mint_has_no_extensions() {
+        let mint = Pubkey::new_unique();
+        assert!(inspect_mint_account(mint, &[0u8; 82]).unwrap().is_none());
+    }
+
+    #[test]
+    fn transfer_fee_config_takes_the_larger_fee() {
+        let mint = Pubkey::new_unique();
+        let data = mint_account(&[(
+            EXTENSION_TRANSFER_FEE_CONFIG,
+            transfer_fee_config((1_000, 300), (2_000, 50)),
+        )]);
+        let transfer_fee = inspect_mint_account(mint, &data).unwrap().unwrap();
+        assert_eq!((transfer_fee.basis_points, transfer_fee.maximum_fee), (300, 1_000));
+    }
+
+    #[test]
+    fn transfer_hook_is_rejected() {
+        let mint = Pubkey::new_unique();
+        let mut hook = vec![0u8; 32];
+        hook.extend_from_slice(Pubkey::new_unique().as_ref());
+        let data = mint_account(&[
+            (EXTENSION_TRANSFER_FEE_CONFIG, transfer_fee_config((0, 0), (0, 0))),
+            (EXTENSION_TRANSFER_HOOK, hook),
+        ]);
+        assert!(matches!(
+            inspect_mint_account(mint, &data),
+            Err(MintRejection::TransferHook(rejected)) if rejected == mint
+        ));
+    }
+
+    #[test]
+    fn hook_extension_without_a_program_is_accepted() {
+        let mint = Pubkey::new_unique();
+        let data = mint_account(&[(EXTENSION_TRANSFER_HOOK, vec![0u8; 64])]);
+        assert!(inspect_mint_account(mint, &data).unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_extension_is_an_error() {
+        let mint = Pubkey::new_unique();
+        let mut data = mint_account(&[(EXTENSION_TRANSFER_FEE_CONFIG, transfer_fee_config((0, 0), (0, 0)))]);
+        data.truncate(data.len() - 1);
+        assert!(matches!(
+            inspect_mint_account(mint, &data),
+            Err(MintRejection::Unfetched(..))
+        ));
+    }
+
+    #[test]
+    fn transfer_fee_initialized_in_the_transaction() {
+        let mint = Pubkey::new_unique();
+        // no config authority, a withdraw authority, then basis points and maximum fee
+        let mut fee = vec![TRANSFER_FEE_EXTENSION_TAG, INITIALIZE_EXTENSION, 0, 1];
+        fee.extend_from_slice(Pubkey::new_unique().as_ref());
+        fee.extend_from_slice(&250u16.to_le_bytes());
+        fee.extend_from_slice(&7_000u64.to_le_bytes());
+        let instructions = [
+            token_2022_instruction(mint, fee),
+            token_2022_instruction(mint, vec![INITIALIZE_MINT2_TAG]),
+        ];
+        let transfer_fee = inspect_mint(mint, TOKEN_2022_PROGRAM, &instructions).unwrap().unwrap();
+        assert_eq!((transfer_fee.basis_points, transfer_fee.maximum_fee), (250, 7_000));
+    }
+
+    #[test]
+    fn mint_created_earlier_has_unknown_extensions() {
+        let mint = Pubkey::new_unique();
+        assert!(matches!(
+            inspect_mint(mint, TOKEN_2022_PROGRAM, &[]),
+            Err(MintRejection::UnknownExtensions(_))
+        ));
+        assert!(inspect_mint(mint, TOKEN_PROGRAM, &[]).unwrap().is_none());
+    }
+}
@@ -1,64 +1,193 @@
-use crate::config::RpcType;
-use crate::meteora::AccountsForBuy;
-use crate::tx_senders::transaction::{TransactionConfig, build_transaction_with_config};
-use crate::tx_senders::{TxResult, TxSender};
-use anyhow::Context;
+use crate::tx_senders::{BundleOutcome, TxResult, TxSender};
+use anyhow::{Context, bail};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::Deserialize;
-use serde_json::json;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
 use solana_sdk::bs58;
-use solana_sdk::hash::Hash;
+use solana_sdk::signature::Signature;
 use solana_sdk::transaction::VersionedTransaction;
+use std::str::FromStr;
+use std::time::Duration;
 use tracing::debug;
 
+// the block engine allows 1 request per second by default
+const BUNDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BUNDLE_MAX_BACKOFF: Duration = Duration::from_secs(8);
+// a blockhash expires after about a minute, a bundle still pending by then is gone
+const BUNDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Copy, Clone)]
+struct BundlePolling {
+    interval: Duration,
+    // the interval doubles after every error, up to max_backoff
+    max_backoff: Duration,
+    timeout: Duration,
+}
+
+impl Default for BundlePolling {
+    fn default() -> Self {
+        BundlePolling {
+            interval: BUNDLE_POLL_INTERVAL,
+            max_backoff: BUNDLE_MAX_BACKOFF,
+            timeout: BUNDLE_TIMEOUT,
+        }
+    }
+}
+
 pub struct JitoTxSender {
     url: String,
+    // sendBundle and the bundle statuses, next to the transactions endpoint
+    bundles_url: String,
     name: String,
     client: Client,
-    tx_config: TransactionConfig,
+    polling: BundlePolling,
 }
 
 impl JitoTxSender {
-    pub fn new(name: String, url: String, tx_config: TransactionConfig, client: Client) -> Self {
-        Self {
+    pub fn new(name: String, url: String, client: Client) -> anyhow::Result<Self> {
+        let Some(base) = url.strip_suffix("/transactions") else {
+            bail!("jito url {} of {} does not end in /transactions", url, name);
+        };
+        let bundles_url = format!("{}/bundles", base);
+        Ok(Self {
             url,
+            bundles_url,
             name,
-            tx_config,
             client,
-        }
-    }
-
-    pub fn build_transaction_with_config(
-        &self,
-        _index: u32,
-        recent_blockhash: Hash,
-        accounts_for_buy: AccountsForBuy,
-    ) -> VersionedTransaction {
-        build_transaction_with_config(&self.tx_config, &RpcType::Jito, recent_blockhash, accounts_for_buy)
+            polling: BundlePolling::default(),
+        })
     }
 }
 
 #[derive(Deserialize)]
 pub struct JitoResponse {
-    //bundle id is response
+    // the transaction signature
     pub result: String,
 }
 
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct StatusesResult<T> {
+    value: Vec<Option<T>>,
+}
+
+#[derive(Deserialize)]
+struct InflightBundleStatus {
+    // Invalid, Pending, Failed or Landed
+    status: String,
+    landed_slot: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct BundleStatus {
+    slot: u64,
+    // {"Ok": null} once executed fine
+    err: Value,
+}
+
+async fn bundles_call<T: DeserializeOwned>(
+    client: &Client,
+    bundles_url: &str,
+    method: &str,
+    params: Value,
+) -> anyhow::Result<T> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params
+    });
+    debug!("{}: {}", method, body.to_string());
+    let response = client.post(bundles_url).json(&body).send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("{} failed: {}", method, body));
+    }
+    let response = serde_json::from_str::<RpcResponse<T>>(&body).context(format!("cannot deserialize {}", method))?;
+    match (response.result, response.error) {
+        (Some(result), _) => Ok(result),
+        (None, error) => Err(anyhow::anyhow!("{} failed: {:?}", method, error)),
+    }
+}
+
+// Polls the in-flight status until the bundle lands, fails or times out. A landed bundle is
+// then looked up in getBundleStatuses, which tells whether its transactions executed fine.
+// Errors, rate limiting included, back off and keep polling until the timeout.
+async fn wait_for_bundle(
+    client: &Client,
+    bundles_url: &str,
+    bundle_id: &str,
+    polling: BundlePolling,
+) -> anyhow::Result<BundleOutcome> {
+    let start = tokio::time::Instant::now();
+    let mut interval = polling.interval;
+    let mut last_error = None;
+    while start.elapsed() < polling.timeout {
+        tokio::time::sleep(interval).await;
+        match poll_bundle(client, bundles_url, bundle_id).await {
+            Ok(Some(outcome)) => return Ok(outcome),
+            Ok(None) => {
+                interval = polling.interval;
+                last_error = None;
+            }
+            Err(err) => {
+                interval = (interval * 2).min(polling.max_backoff);
+                debug!("bundle {} status: {:#}, retrying in {:?}", bundle_id, err, interval);
+                last_error = Some(err);
+            }
+        }
+    }
+    match last_error {
+        // never got an answer, nothing is known of the bundle
+        Some(err) => Err(err.context(format!(
+            "bundle {} still unknown after {:?}",
+            bundle_id, polling.timeout
+        ))),
+        None => Ok(BundleOutcome::Dropped),
+    }
+}
+
+// None while the bundle is pending
+async fn poll_bundle(client: &Client, bundles_url: &str, bundle_id: &str) -> anyhow::Result<Option<BundleOutcome>> {
+    let inflight: StatusesResult<InflightBundleStatus> =
+        bundles_call(client, bundles_url, "getInflightBundleStatuses", json!([[bundle_id]])).await?;
+    let Some(Some(inflight)) = inflight.value.into_iter().next() else {
+        return Ok(None);
+    };
+    match inflight.status.as_str() {
+        "Failed" => return Ok(Some(BundleOutcome::Failed)),
+        "Landed" => {}
+        // Invalid right after sending only means not indexed yet
+        _ => return Ok(None),
+    }
+
+    let statuses: StatusesResult<BundleStatus> =
+        bundles_call(client, bundles_url, "getBundleStatuses", json!([[bundle_id]])).await?;
+    Ok(Some(match statuses.value.into_iter().next().flatten() {
+        Some(status) if status.err.get("Ok").is_none() => BundleOutcome::Failed,
+        Some(status) => BundleOutcome::Landed { slot: status.slot },
+        None => BundleOutcome::Landed {
+            slot: inflight.landed_slot.unwrap_or_default(),
+        },
+    }))
+}
+
 #[async_trait]
 impl TxSender for JitoTxSender {
     fn name(&self) -> String {
         self.name.clone()
     }
 
-    async fn send_transaction(
-        &self,
-        index: u32,
-        recent_blockhash: Hash,
-        accounts_for_buy: AccountsForBuy,
-    ) -> anyhow::Result<TxResult> {
-        let tx = self.build_transaction_with_config(index, recent_blockhash, accounts_for_buy);
-        let tx_bytes = bincode::serialize(&tx).context("cannot serialize tx to bincode")?;
+    async fn send_transaction(&self, _index: u32, transaction: &VersionedTransaction) -> anyhow::Result<TxResult> {
+        let tx_bytes = bincode::serialize(transaction).context("cannot serialize tx to bincode")?;
         let encoded_transaction = bs58::encode(tx_bytes).into_string();
         let body = json!({
             "jsonrpc": "2.0",
@@ -74,6 +203,144 @@ impl TxSender for JitoTxSender {
             return Err(anyhow::anyhow!("failed to send tx: {}", body));
         }
         let parsed_resp = serde_json::from_str::<JitoResponse>(&body).context("cannot deserialize signature")?;
-        Ok(TxResult::BundleID(parsed_resp.result))
+        let signature = Signature::from_str(&parsed_resp.result).context("cannot parse signature")?;
+        Ok(TxResult::Signature(signature))
+    }
+
+    async fn send_bundle(&self, transactions: &[VersionedTransaction]) -> anyhow::Result<TxResult> {
+        let mut encoded_transactions = Vec::new();
+        for transaction in transactions {
+            let tx_bytes = bincode::serialize(transaction).context("cannot serialize tx to bincode")?;
+            encoded_transactions.push(bs58::encode(tx_bytes).into_string());
+        }
+        let bundle_id: String = bundles_call(
+            &self.client,
+            &self.bundles_url,
+            "sendBundle",
+            json!([encoded_transactions]),
+        )
+        .await?;
+
+        Ok(TxResult::BundleID(bundle_id))
+    }
+
+    async fn wait_for_bundle(&self, bundle_id: &str) -> anyhow::Result<BundleOutcome> {
+        wait_for_bundle(&self.client, &self.bundles_url, bundle_id, self.polling).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx_senders::tests::mock_rpc;
+
+    // Answers every call with respond(method, call number).
+    async fn mock_block_engine(respond: impl Fn(&str, usize) -> (u16, Value) + Send + Sync + 'static) -> String {
+        let url = mock_rpc(move |request, call| respond(request["method"].as_str().unwrap(), call)).await;
+        format!("{}/api/v1/transactions", url)
+    }
+
+    fn sender(url: String) -> JitoTxSender {
+        JitoTxSender {
+            polling: BundlePolling {
+                interval: Duration::from_millis(10),
+                max_backoff: Duration::from_millis(40),
+                timeout: Duration::from_millis(500),
+            },
+            ..JitoTxSender::new("jito".to_string(), url, Client::new()).unwrap()
+        }
+    }
+
+    fn statuses(value: Value) -> (u16, Value) {
+        (
+            200,
+            json!({"jsonrpc": "2.0", "id": 1, "result": {"context": {"slot": 1}, "value": [value]}}),
+        )
+    }
+
+    fn inflight(status: &str) -> (u16, Value) {
+        statuses(json!({"bundle_id": "bundle", "status": status, "landed_slot": null}))
+    }
+
+    #[tokio::test]
+    async fn landed_bundle() {
+        let url = mock_block_engine(|method, _| match method {
+            "getInflightBundleStatuses" => {
+                statuses(json!({"bundle_id": "bundle", "status": "Landed", "landed_slot": 42}))
+            }
+            _ => statuses(json!({"bundle_id": "bundle", "slot": 42, "err": {"Ok": null}})),
+        })
+        .await;
+        let outcome = sender(url).wait_for_bundle("bundle").await.unwrap();
+        assert_eq!(outcome, BundleOutcome::Landed { slot: 42 });
+    }
+
+    #[tokio::test]
+    async fn failed_bundle() {
+        let url = mock_block_engine(|_, _| inflight("Failed")).await;
+        let outcome = sender(url).wait_for_bundle("bundle").await.unwrap();
+        assert_eq!(outcome, BundleOutcome::Failed);
+    }
+
+    #[tokio::test]
+    async fn landed_bundle_with_a_failed_transaction() {
+        let url = mock_block_engine(|method, _| match method {
+            "getInflightBundleStatuses" => inflight("Landed"),
+            _ => statuses(json!({"bundle_id": "bundle", "slot": 42, "err": {"Err": "custom"}})),
+        })
+        .await;
+        let outcome = sender(url).wait_for_bundle("bundle").await.unwrap();
+        assert_eq!(outcome, BundleOutcome::Failed);
+    }
+
+    #[tokio::test]
+    async fn invalid_bundle_is_dropped() {
+        let url = mock_block_engine(|_, _| inflight("Invalid")).await;
+        let outcome = sender(url).wait_for_bundle("bundle").await.unwrap();
+        assert_eq!(outcome, BundleOutcome::Dropped);
+    }
+
+    #[tokio::test]
+    async fn pending_bundle_times_out() {
+        let url = mock_block_engine(|_, _| inflight("Pending")).await;
+        let outcome = sender(url).wait_for_bundle("bundle").await.unwrap();
+        assert_eq!(outcome, BundleOutcome::Dropped);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_polls_keep_going() {
+        let url = mock_block_engine(|method, call| match (method, call) {
+            (_, 0..3) => (
+                429,
+                json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32097, "message": "rate limited"}}),
+            ),
+            ("getInflightBundleStatuses", _) => inflight("Landed"),
+            _ => statuses(json!({"bundle_id": "bundle", "slot": 7, "err": {"Ok": null}})),
+        })
+        .await;
+        let outcome = sender(url).wait_for_bundle("bundle").await.unwrap();
+        assert_eq!(outcome, BundleOutcome::Landed { slot: 7 });
+    }
+
+    #[test]
+    fn url_without_transactions_is_refused() {
+        let url = "https://ny.mainnet.block-engine.jito.wtf/api/v1/bundles".to_string();
+        assert!(JitoTxSender::new("jito".to_string(), url, Client::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn plain_send_is_a_signature() {
+        let signature = Signature::from([7u8; 64]);
+        let url =
+            mock_block_engine(move |_, _| (200, json!({"jsonrpc": "2.0", "id": 1, "result": signature.to_string()})))
+                .await;
+        let result = sender(url).send_transaction(0, &VersionedTransaction::default()).await.unwrap();
+        assert!(matches!(result, TxResult::Signature(sent) if sent == signature));
+    }
+
+    #[tokio::test]
+    async fn unreachable_block_engine_is_an_error() {
+        let url = mock_block_engine(|_, _| (500, json!({}))).await;
+        assert!(sender(url).wait_for_bundle("bundle").await.is_err());
     }
 }
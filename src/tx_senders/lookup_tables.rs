@@ -0,0 +1,58 @@
+use crate::config::PingThingsArgs;
+use crate::tx_senders::constants::{
+    METEORA_DAMM_V2_EVENT_AUTHORITY, METEORA_DAMM_V2_PROGRAM, METEORA_DLMM_EVENT_AUTHORITY, METEORA_DLMM_PROGRAM,
+    METEORA_POOLS_PROGRAM, METEORA_VAULT_PROGRAM, TOKEN_2022_PROGRAM, TOKEN_PROGRAM, WSOL_MINT,
+};
+use anyhow::Context;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::address_lookup_table::state::AddressLookupTable;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::{compute_budget, system_program};
+use spl_associated_token_account::get_associated_token_address;
+use std::str::FromStr;
+
+// Accounts referenced by every trade, whatever the pool. The owner signs and cannot be looked up,
+// tip accounts stay static as block engines do not count tips paid to looked up accounts.
+pub fn static_accounts(owner: &Pubkey) -> Vec<Pubkey> {
+    vec![
+        compute_budget::id(),
+        system_program::id(),
+        spl_associated_token_account::id(),
+        TOKEN_PROGRAM,
+        TOKEN_2022_PROGRAM,
+        WSOL_MINT,
+        get_associated_token_address(owner, &WSOL_MINT),
+        METEORA_POOLS_PROGRAM,
+        METEORA_VAULT_PROGRAM,
+        METEORA_DLMM_PROGRAM,
+        METEORA_DLMM_EVENT_AUTHORITY,
+        METEORA_DAMM_V2_PROGRAM,
+        METEORA_DAMM_V2_EVENT_AUTHORITY,
+    ]
+}
+
+pub async fn fetch_lookup_table(rpc: &RpcClient, key: Pubkey) -> anyhow::Result<AddressLookupTableAccount> {
+    let data = rpc.get_account_data(&key).await.context(format!("cannot fetch lookup table {}", key))?;
+    let lookup_table = AddressLookupTable::deserialize(&data)
+        .map_err(|err| anyhow::anyhow!("invalid lookup table {}: {}", key, err))?;
+    Ok(AddressLookupTableAccount {
+        key,
+        addresses: lookup_table.addresses.to_vec(),
+    })
+}
+
+// The configured tables, fetched once: accounts added to a table later are not picked up.
+pub async fn load_lookup_tables(config: &PingThingsArgs) -> anyhow::Result<Vec<AddressLookupTableAccount>> {
+    if config.lookup_tables.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rpc = config.http_rpc_client().context("http_rpc is required to fetch lookup_tables")?;
+
+    let mut lookup_tables = Vec::new();
+    for key in &config.lookup_tables {
+        let key = Pubkey::from_str(key).context(format!("invalid lookup table {}", key))?;
+        lookup_tables.push(fetch_lookup_table(&rpc, key).await?);
+    }
+    Ok(lookup_tables)
+}
@@ -1,23 +1,27 @@
 use crate::config::{RpcConfig, RpcType};
-use crate::meteora::AccountsForBuy;
 use crate::tx_senders::bloxroute::BloxrouteTxSender;
 use crate::tx_senders::jito::JitoTxSender;
 use crate::tx_senders::nextblock::NextblockTxSender;
 use crate::tx_senders::solana_rpc::GenericRpc;
-use crate::tx_senders::transaction::TransactionConfig;
+use anyhow::Context;
 use async_trait::async_trait;
 use reqwest::Client;
-use solana_sdk::hash::Hash;
 use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
 use std::sync::Arc;
 use tracing::info;
 
 pub mod bloxroute;
 pub mod constants;
 pub mod jito;
+pub mod lookup_tables;
 pub mod nextblock;
+pub mod nonce;
 pub mod solana_rpc;
+pub mod template;
+pub mod tip_accounts;
 pub mod transaction;
+pub mod wallets;
 
 #[derive(Debug, Clone)]
 pub enum TxResult {
@@ -25,61 +29,111 @@ pub enum TxResult {
     BundleID(String),
 }
 
-impl Into<String> for TxResult {
-    fn into(self) -> String {
-        match self {
+impl From<TxResult> for String {
+    fn from(result: TxResult) -> Self {
+        match result {
             TxResult::Signature(sig) => sig.to_string(),
             TxResult::BundleID(bundle_id) => bundle_id,
         }
     }
 }
 
+// what became of a bundle, from the block engine's point of view
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleOutcome {
+    Landed { slot: u64 },
+    // simulated or executed with an error
+    Failed,
+    // unknown to the block engine or still pending at the timeout
+    Dropped,
+}
+
 #[async_trait]
 pub trait TxSender: Sync + Send {
     fn name(&self) -> String;
-    async fn send_transaction(
-        &self,
-        index: u32,
-        recent_blockhash: Hash,
-        accounts_for_buy: AccountsForBuy,
-    ) -> anyhow::Result<TxResult>;
+    async fn send_transaction(&self, index: u32, transaction: &VersionedTransaction) -> anyhow::Result<TxResult>;
+    // executed all or nothing, in order; only block engines take bundles
+    async fn send_bundle(&self, _transactions: &[VersionedTransaction]) -> anyhow::Result<TxResult> {
+        Err(anyhow::anyhow!("{} does not accept bundles", self.name()))
+    }
+    // waits until a bundle sent through send_bundle lands, fails or is dropped
+    async fn wait_for_bundle(&self, _bundle_id: &str) -> anyhow::Result<BundleOutcome> {
+        Err(anyhow::anyhow!("{} does not accept bundles", self.name()))
+    }
 }
 
-pub fn create_tx_sender(
-    name: String,
-    rpc_config: RpcConfig,
-    tx_config: TransactionConfig,
-    client: Client,
-) -> Arc<dyn TxSender> {
+pub fn create_tx_sender(name: String, rpc_config: RpcConfig, client: Client) -> anyhow::Result<Arc<dyn TxSender>> {
     info!("create_tx_sender {:?}", rpc_config.rpc_type);
-    match rpc_config.rpc_type {
-        RpcType::SolanaRpc => {
-            let tx_sender = GenericRpc::new(name, rpc_config.url, tx_config, RpcType::SolanaRpc);
-            Arc::new(tx_sender)
-        }
-        RpcType::Jito => {
-            let tx_sender = JitoTxSender::new(name, rpc_config.url, tx_config, client);
-            Arc::new(tx_sender)
-        }
+    let tx_sender: Arc<dyn TxSender> = match rpc_config.rpc_type {
+        RpcType::SolanaRpc => Arc::new(GenericRpc::new(name, rpc_config.url)),
+        RpcType::Jito => Arc::new(JitoTxSender::new(name, rpc_config.url, client)?),
         RpcType::Bloxroute => {
-            let tx_sender = BloxrouteTxSender::new(
-                name,
-                rpc_config.url,
-                tx_config,
-                client,
-                rpc_config.auth.expect("failed to parse bloxroute auth key"),
-            );
-            Arc::new(tx_sender)
+            let auth = rpc_config.auth.context("bloxroute needs an auth key")?;
+            Arc::new(BloxrouteTxSender::new(name, rpc_config.url, client, auth))
         }
         RpcType::Nextblock => {
-            let tx_sender = NextblockTxSender::new(
-                name,
-                rpc_config.url,
-                tx_config,
-                client,
-                rpc_config.auth.expect("failed to parse nextlock auth key"),
-            );
-            Arc::new(tx_sender)
+            let auth = rpc_config.auth.context("nextblock needs an auth key")?;
+            Arc::new(NextblockTxSender::new(name, rpc_config.url, client, auth))
         }
+    };
+    Ok(tx_sender)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use serde_json::Value;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // JSON-RPC server answering every call with respond(request, call number), one request per connection.
+    pub(crate) async fn mock_rpc(respond: impl Fn(&Value, usize) -> (u16, Value) + Send + Sync + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let calls = calls.clone();
+                let respond = respond.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0u8; 4096];
+                    let body = loop {
+                        let read = stream.read(&mut buffer).await.unwrap();
+                        request.extend_from_slice(&buffer[..read]);
+                        let text = String::from_utf8_lossy(&request);
+                        let Some(header_end) = text.find("\r\n\r\n") else {
+                            continue;
+                        };
+                        let content_length = text[..header_end]
+                            .lines()
+                            .find_map(|line| {
+                                line.to_ascii_lowercase().strip_prefix("content-length:").map(str::to_string)
+                            })
+                            .map_or(0, |length| length.trim().parse().unwrap());
+                        if request.len() >= header_end + 4 + content_length {
+                            break request[header_end + 4..].to_vec();
+                        }
+                    };
+                    let request: Value = serde_json::from_slice(&body).unwrap();
+                    let (status, mut response) = respond(&request, calls.fetch_add(1, Ordering::Relaxed));
+                    // the rpc client matches responses to requests by id
+                    if response.get("id").is_some() {
+                        response["id"] = request["id"].clone();
+                    }
+                    let response = response.to_string();
+                    let http = format!(
+                        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        response.len(),
+                        response
+                    );
+                    stream.write_all(http.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        url
     }
 }
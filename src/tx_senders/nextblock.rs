@@ -1,8 +1,5 @@
 use std::str::FromStr;
 
-use crate::config::RpcType;
-use crate::meteora::AccountsForBuy;
-use crate::tx_senders::transaction::{TransactionConfig, build_transaction_with_config};
 use crate::tx_senders::{TxResult, TxSender};
 use anyhow::Context;
 use async_trait::async_trait;
@@ -11,7 +8,6 @@ use reqwest::Client;
 use reqwest::header::HeaderMap;
 use serde::Deserialize;
 use serde_json::json;
-use solana_sdk::hash::Hash;
 use solana_sdk::signature::Signature;
 use solana_sdk::transaction::VersionedTransaction;
 use tracing::debug;
@@ -21,28 +17,17 @@ pub struct NextblockTxSender {
     name: String,
     client: Client,
     auth: String,
-    tx_config: TransactionConfig,
 }
 
 impl NextblockTxSender {
-    pub fn new(name: String, url: String, tx_config: TransactionConfig, client: Client, auth: String) -> Self {
+    pub fn new(name: String, url: String, client: Client, auth: String) -> Self {
         Self {
             url,
             name,
-            tx_config,
             client,
             auth,
         }
     }
-
-    pub fn build_transaction_with_config(
-        &self,
-        _index: u32,
-        recent_blockhash: Hash,
-        accounts_for_buy: AccountsForBuy,
-    ) -> VersionedTransaction {
-        build_transaction_with_config(&self.tx_config, &RpcType::Nextblock, recent_blockhash, accounts_for_buy)
-    }
 }
 
 #[derive(Deserialize)]
@@ -56,14 +41,8 @@ impl TxSender for NextblockTxSender {
         self.name.clone()
     }
 
-    async fn send_transaction(
-        &self,
-        index: u32,
-        recent_blockhash: Hash,
-        accounts_for_buy: AccountsForBuy,
-    ) -> anyhow::Result<TxResult> {
-        let tx = self.build_transaction_with_config(index, recent_blockhash, accounts_for_buy);
-        let tx_bytes = bincode::serialize(&tx).context("cannot serialize tx to bincode")?;
+    async fn send_transaction(&self, _index: u32, transaction: &VersionedTransaction) -> anyhow::Result<TxResult> {
+        let tx_bytes = bincode::serialize(transaction).context("cannot serialize tx to bincode")?;
         let encoded_transaction = base64::encode(tx_bytes);
         let mut headers = HeaderMap::new();
         headers.insert("Content-Type", "application/json".parse().unwrap());
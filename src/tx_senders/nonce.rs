@@ -0,0 +1,107 @@
+use crate::config::PingThingsArgs;
+use anyhow::{Context, bail};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::nonce_utils::data_from_account;
+use solana_client::nonce_utils::nonblocking::get_account_with_commitment;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+const NONCE_POLL_INTERVAL: Duration = Duration::from_millis(400);
+// durable nonce transactions never expire, past this an unchanged nonce is taken as unused
+const NONCE_RELEASE_TIMEOUT: Duration = Duration::from_secs(90);
+
+// a nonce account and the nonce it holds, signed in place of a recent blockhash
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DurableNonce {
+    pub account: Pubkey,
+    pub nonce: Hash,
+}
+
+pub type SharedNoncePool = Arc<NoncePool>;
+
+// Nonce accounts free for the next trade. Every sender signs the trade against the same
+// nonce, so at most one of their transactions executes. An account is free again once its
+// nonce has advanced, more accounts allow more trades in flight.
+pub struct NoncePool {
+    rpc: RpcClient,
+    free: Mutex<Vec<DurableNonce>>,
+}
+
+impl NoncePool {
+    pub fn acquire(&self) -> Option<DurableNonce> {
+        self.free.lock().unwrap().pop()
+    }
+
+    // gives the account back with its next nonce, or with the same one if nothing landed
+    pub fn release(self: &Arc<Self>, used: DurableNonce) {
+        let nonce_pool = self.clone();
+        tokio::spawn(async move {
+            let start = tokio::time::Instant::now();
+            let mut next = used;
+            while start.elapsed() < NONCE_RELEASE_TIMEOUT {
+                tokio::time::sleep(NONCE_POLL_INTERVAL).await;
+                match fetch_nonce(&nonce_pool.rpc, used.account).await {
+                    Ok(current) if current.nonce != used.nonce => {
+                        next = current;
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(err) => warn!("cannot refresh nonce account {}: {:#}", used.account, err),
+                }
+            }
+            nonce_pool.free.lock().unwrap().push(next);
+        });
+    }
+}
+
+// confirmed, a processed nonce can still be rolled back with its fork
+pub async fn fetch_nonce(rpc: &RpcClient, account: Pubkey) -> anyhow::Result<DurableNonce> {
+    let nonce_account = get_account_with_commitment(rpc, &account, CommitmentConfig::confirmed()).await?;
+    let data = data_from_account(&nonce_account)?;
+    Ok(DurableNonce {
+        account,
+        nonce: data.blockhash(),
+    })
+}
+
+// The configured nonce accounts, their authority must be the bot's wallet.
+pub async fn load_nonce_pool(config: &PingThingsArgs) -> anyhow::Result<Option<SharedNoncePool>> {
+    if config.nonce_accounts.is_empty() {
+        return Ok(None);
+    }
+    let rpc = config.http_rpc_client().context("http_rpc is required to use nonce_accounts")?;
+    let owner = Keypair::from_base58_string(&config.private_key).pubkey();
+
+    let mut free = Vec::new();
+    for account in &config.nonce_accounts {
+        let account = Pubkey::from_str(account).context(format!("invalid nonce account {}", account))?;
+        let nonce_account = get_account_with_commitment(&rpc, &account, CommitmentConfig::confirmed())
+            .await
+            .context(format!("cannot fetch nonce account {}", account))?;
+        let data = data_from_account(&nonce_account).context(format!("invalid nonce account {}", account))?;
+        if data.authority != owner {
+            bail!(
+                "nonce account {} is authorized to {}, not {}",
+                account,
+                data.authority,
+                owner
+            );
+        }
+        free.push(DurableNonce {
+            account,
+            nonce: data.blockhash(),
+        });
+    }
+    info!("{} nonce accounts loaded", free.len());
+
+    Ok(Some(Arc::new(NoncePool {
+        rpc,
+        free: Mutex::new(free),
+    })))
+}
@@ -1,13 +1,10 @@
-use crate::config::RpcType;
-use crate::meteora::AccountsForBuy;
-use crate::tx_senders::transaction::{TransactionConfig, build_transaction_with_config};
 use crate::tx_senders::{TxResult, TxSender};
 use anyhow::Context;
 use async_trait::async_trait;
 use serde::Serialize;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_config::RpcSendTransactionConfig;
-use solana_sdk::hash::Hash;
+use solana_sdk::transaction::VersionedTransaction;
 use solana_transaction_status::UiTransactionEncoding;
 use std::sync::Arc;
 
@@ -15,8 +12,6 @@ use std::sync::Arc;
 pub struct GenericRpc {
     pub name: String,
     pub http_rpc: Arc<RpcClient>,
-    tx_config: TransactionConfig,
-    rpc_type: RpcType,
 }
 
 #[derive(Serialize, Debug)]
@@ -32,14 +27,9 @@ pub struct TxMetrics {
 }
 
 impl GenericRpc {
-    pub fn new(name: String, url: String, config: TransactionConfig, rpc_type: RpcType) -> Self {
+    pub fn new(name: String, url: String) -> Self {
         let http_rpc = Arc::new(RpcClient::new(url));
-        GenericRpc {
-            name,
-            http_rpc,
-            tx_config: config,
-            rpc_type,
-        }
+        GenericRpc { name, http_rpc }
     }
 }
 
@@ -49,26 +39,16 @@ impl TxSender for GenericRpc {
         self.name.clone()
     }
 
-    async fn send_transaction(
-        &self,
-        _index: u32,
-        recent_blockhash: Hash,
-        accounts_for_buy: AccountsForBuy,
-    ) -> anyhow::Result<TxResult> {
-        let transaction =
-            build_transaction_with_config(&self.tx_config, &self.rpc_type, recent_blockhash, accounts_for_buy);
+    async fn send_transaction(&self, _index: u32, transaction: &VersionedTransaction) -> anyhow::Result<TxResult> {
         let sig = self
             .http_rpc
-            .send_transaction_with_config(
-                &transaction,
-                RpcSendTransactionConfig {
-                    skip_preflight: true,
-                    preflight_commitment: None,
-                    encoding: Some(UiTransactionEncoding::Base64),
-                    max_retries: None,
-                    min_context_slot: None,
-                },
-            )
+            .send_transaction_with_config(transaction, RpcSendTransactionConfig {
+                skip_preflight: true,
+                preflight_commitment: None,
+                encoding: Some(UiTransactionEncoding::Base64),
+                max_retries: None,
+                min_context_slot: None,
+            })
             .await
             .context(format!("Failed to send transaction for {}", self.name))?;
         Ok(TxResult::Signature(sig))
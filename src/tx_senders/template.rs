@@ -0,0 +1,566 @@
+use crate::config::RpcType;
+use crate::meteora::instructions::SWAP_DISC;
+use crate::meteora::{AccountsForBuy, IX_DISCRIMINATOR_SIZE, QuoteSide};
+use crate::priority_fee::PriorityFees;
+use crate::tx_senders::nonce::DurableNonce;
+use crate::tx_senders::transaction::{
+    BuyAccounts, PreparedInstructions, TransactionConfig, build_prefix_instructions, build_tip_transaction,
+};
+use anyhow::{Context, bail};
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::message::v0::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_sdk::{system_instruction, system_program};
+use spl_associated_token_account::get_associated_token_address;
+use std::sync::Arc;
+use tracing::warn;
+
+// offset of minimum_out_amount in the swap instruction data
+const SWAP_MIN_OUT_OFFSET: usize = IX_DISCRIMINATOR_SIZE + 8;
+// offset of the u64 in SetComputeUnitPrice (borsh tag) and in a system transfer (bincode u32 tag)
+const COMPUTE_UNIT_PRICE_OFFSET: usize = 1;
+const TIP_LAMPORTS_OFFSET: usize = 4;
+// SystemInstruction tags, bincode u32
+const SYSTEM_TRANSFER_TAG: u32 = 2;
+const SYSTEM_ADVANCE_NONCE_TAG: u32 = 4;
+
+// What a sender signs: a dynamic AMM buy goes through the pre-compiled skeleton, other
+// buys are prepared per sender as amounts may differ, everything else is compiled as is.
+#[derive(Debug, Clone)]
+pub enum Trade {
+    AmmBuy(Box<AccountsForBuy>),
+    Buy(Box<BuyAccounts>),
+    Prepared(Arc<PreparedInstructions>),
+}
+
+impl Trade {
+    pub fn buy(buy_accounts: BuyAccounts) -> Self {
+        match buy_accounts {
+            BuyAccounts::Amm(accounts_for_buy) => Trade::AmmBuy(Box::new(accounts_for_buy)),
+            buy_accounts => Trade::Buy(Box::new(buy_accounts)),
+        }
+    }
+}
+
+// Accounts of a dynamic AMM buy that change from pool to pool.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AmmBuySlot {
+    Pool,
+    TokenMint,
+    UserTokenAccount,
+    AVault,
+    BVault,
+    ATokenVault,
+    BTokenVault,
+    AVaultLpMint,
+    BVaultLpMint,
+    AVaultLp,
+    BVaultLp,
+    ProtocolTokenFee,
+}
+
+impl AmmBuySlot {
+    const ALL: [AmmBuySlot; 12] = [
+        AmmBuySlot::Pool,
+        AmmBuySlot::TokenMint,
+        AmmBuySlot::UserTokenAccount,
+        AmmBuySlot::AVault,
+        AmmBuySlot::BVault,
+        AmmBuySlot::ATokenVault,
+        AmmBuySlot::BTokenVault,
+        AmmBuySlot::AVaultLpMint,
+        AmmBuySlot::BVaultLpMint,
+        AmmBuySlot::AVaultLp,
+        AmmBuySlot::BVaultLp,
+        AmmBuySlot::ProtocolTokenFee,
+    ];
+
+    fn resolve(&self, accounts_for_buy: &AccountsForBuy, user_token_account: &Pubkey) -> Pubkey {
+        match self {
+            AmmBuySlot::Pool => accounts_for_buy.pool,
+            AmmBuySlot::TokenMint => accounts_for_buy.token_mint,
+            AmmBuySlot::UserTokenAccount => *user_token_account,
+            AmmBuySlot::AVault => accounts_for_buy.a_vault,
+            AmmBuySlot::BVault => accounts_for_buy.b_vault,
+            AmmBuySlot::ATokenVault => accounts_for_buy.a_token_vault,
+            AmmBuySlot::BTokenVault => accounts_for_buy.b_token_vault,
+            AmmBuySlot::AVaultLpMint => accounts_for_buy.a_vault_lp_mint,
+            AmmBuySlot::BVaultLpMint => accounts_for_buy.b_vault_lp_mint,
+            AmmBuySlot::AVaultLp => accounts_for_buy.a_vault_lp,
+            AmmBuySlot::BVaultLp => accounts_for_buy.b_vault_lp,
+            // charged on the input side, WSOL
+            AmmBuySlot::ProtocolTokenFee => match accounts_for_buy.quote_side {
+                QuoteSide::A => accounts_for_buy.protocol_token_a_fee,
+                QuoteSide::B => accounts_for_buy.protocol_token_b_fee,
+            },
+        }
+    }
+}
+
+// A dynamic AMM buy compiled once with placeholder accounts. Instruction account
+// indexes never change, so patching the keys in place keeps the message valid.
+#[derive(Debug, Clone)]
+struct AmmBuySkeleton {
+    message: Message,
+    // index in message.account_keys of every slot
+    slots: Vec<(AmmBuySlot, usize)>,
+    // index in message.instructions of the swap
+    swap_instruction: usize,
+}
+
+impl AmmBuySkeleton {
+    fn compile(
+        tx_config: &TransactionConfig,
+        prefix: &[Instruction],
+        prefix_instructions: &PrefixInstructions,
+    ) -> anyhow::Result<Self> {
+        let owner = tx_config.keypair.pubkey();
+        let placeholders: Vec<(AmmBuySlot, Pubkey)> =
+            AmmBuySlot::ALL.iter().map(|slot| (*slot, Pubkey::new_unique())).collect();
+        let placeholder =
+            |slot: AmmBuySlot| placeholders.iter().find(|(placeholder_slot, _)| *placeholder_slot == slot).unwrap().1;
+
+        let accounts_for_buy = AccountsForBuy {
+            pool: placeholder(AmmBuySlot::Pool),
+            token_mint: placeholder(AmmBuySlot::TokenMint),
+            quote_side: QuoteSide::B,
+            a_vault: placeholder(AmmBuySlot::AVault),
+            b_vault: placeholder(AmmBuySlot::BVault),
+            a_token_vault: placeholder(AmmBuySlot::ATokenVault),
+            b_token_vault: placeholder(AmmBuySlot::BTokenVault),
+            a_vault_lp_mint: placeholder(AmmBuySlot::AVaultLpMint),
+            b_vault_lp_mint: placeholder(AmmBuySlot::BVaultLpMint),
+            a_vault_lp: placeholder(AmmBuySlot::AVaultLp),
+            b_vault_lp: placeholder(AmmBuySlot::BVaultLp),
+            protocol_token_a_fee: placeholder(AmmBuySlot::ProtocolTokenFee),
+            protocol_token_b_fee: placeholder(AmmBuySlot::ProtocolTokenFee),
+            token_a_amount: 0,
+            token_b_amount: 0,
+            trade_fee_bps: None,
+        };
+        // the ATA is derived from the mint, its placeholder is the real derivation
+        let user_token_account = get_associated_token_address(&owner, &accounts_for_buy.token_mint);
+
+        let mut instructions = prefix.to_vec();
+        instructions.extend(PreparedInstructions::buy(tx_config, BuyAccounts::Amm(accounts_for_buy)).instructions);
+        let message = Message::try_compile(&owner, &instructions, &tx_config.lookup_tables, Hash::default())?;
+        // patched per trade, so they must be static keys
+        prefix_instructions.static_keys(&message)?;
+
+        let mut slots = Vec::new();
+        for slot in AmmBuySlot::ALL {
+            let key = slot.resolve(&accounts_for_buy, &user_token_account);
+            let index = message
+                .account_keys
+                .iter()
+                .position(|account_key| *account_key == key)
+                .context(format!("{:?} is loaded from a lookup table", slot))?;
+            slots.push((slot, index));
+        }
+        let swap_instruction = message
+            .instructions
+            .iter()
+            .position(|instruction| instruction.data.starts_with(&SWAP_DISC))
+            .context("no swap instruction")?;
+
+        Ok(AmmBuySkeleton {
+            message,
+            slots,
+            swap_instruction,
+        })
+    }
+}
+
+// Where the patched instructions sit in the prefix, and so in every message as the prefix comes first.
+#[derive(Debug, Copy, Clone)]
+struct PrefixInstructions {
+    nonce: Option<usize>,
+    compute_unit_price: Option<usize>,
+    tip: Option<usize>,
+}
+
+impl PrefixInstructions {
+    fn locate(prefix: &[Instruction]) -> Self {
+        let set_compute_unit_price = ComputeBudgetInstruction::set_compute_unit_price(0);
+        let system_instruction = |tag: u32| {
+            move |instruction: &Instruction| {
+                instruction.program_id == system_program::id() && instruction.data.starts_with(&tag.to_le_bytes())
+            }
+        };
+        PrefixInstructions {
+            nonce: prefix.iter().position(system_instruction(SYSTEM_ADVANCE_NONCE_TAG)),
+            compute_unit_price: prefix.iter().position(|instruction| {
+                instruction.program_id == compute_budget::id()
+                    && instruction.data.first() == set_compute_unit_price.data.first()
+            }),
+            tip: prefix.iter().position(system_instruction(SYSTEM_TRANSFER_TAG)),
+        }
+    }
+
+    fn patch_instructions(
+        &self,
+        instructions: &mut [Instruction],
+        fees: PriorityFees,
+        tip_account: Option<Pubkey>,
+        nonce: Option<DurableNonce>,
+    ) {
+        if let (Some(index), Some(nonce)) = (self.nonce, nonce) {
+            instructions[index].accounts[0].pubkey = nonce.account;
+        }
+        if let Some(index) = self.compute_unit_price {
+            patch_u64(
+                &mut instructions[index].data,
+                COMPUTE_UNIT_PRICE_OFFSET,
+                fees.compute_unit_price,
+            );
+        }
+        if let Some(index) = self.tip {
+            patch_u64(&mut instructions[index].data, TIP_LAMPORTS_OFFSET, fees.tip);
+            if let Some(tip_account) = tip_account {
+                instructions[index].accounts[1].pubkey = tip_account;
+            }
+        }
+    }
+
+    // (nonce account, tip account) indexes in message.account_keys
+    fn static_keys(&self, message: &Message) -> anyhow::Result<(Option<usize>, Option<usize>)> {
+        let static_key = |instruction: Option<usize>, account: usize, name: &str| {
+            instruction
+                .map(|index| {
+                    let key_index = message.instructions[index].accounts[account] as usize;
+                    if key_index >= message.account_keys.len() {
+                        bail!("the {} is loaded from a lookup table, a template cannot patch it", name);
+                    }
+                    Ok(key_index)
+                })
+                .transpose()
+        };
+        Ok((
+            static_key(self.nonce, 0, "nonce account")?,
+            static_key(self.tip, 1, "tip account")?,
+        ))
+    }
+
+    // nonce and tip accounts are static keys, all writable non-signers, any of them fits the slot
+    fn patch_message(
+        &self,
+        message: &mut Message,
+        fees: PriorityFees,
+        tip_account: Option<Pubkey>,
+        nonce: Option<DurableNonce>,
+    ) -> anyhow::Result<()> {
+        let (nonce_key, tip_key) = self.static_keys(message)?;
+        if let (Some(key_index), Some(nonce)) = (nonce_key, nonce) {
+            message.account_keys[key_index] = nonce.account;
+        }
+        if let Some(index) = self.compute_unit_price {
+            patch_u64(
+                &mut message.instructions[index].data,
+                COMPUTE_UNIT_PRICE_OFFSET,
+                fees.compute_unit_price,
+            );
+        }
+        if let Some(index) = self.tip {
+            patch_u64(&mut message.instructions[index].data, TIP_LAMPORTS_OFFSET, fees.tip);
+        }
+        if let (Some(key_index), Some(tip_account)) = (tip_key, tip_account) {
+            message.account_keys[key_index] = tip_account;
+        }
+        Ok(())
+    }
+}
+
+fn patch_u64(data: &mut [u8], offset: usize, value: u64) {
+    data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+// Per sender: compute budget and tip instructions built once, plus the dynamic AMM
+// buy skeleton, so the hot path only patches accounts, amounts and blockhash, then signs.
+// Only the dynamic AMM buy has a skeleton: DAMM v2 and DLMM buys, mirrored swaps and
+// sells reuse the prefix but still compile their message per trade.
+pub struct TransactionTemplate {
+    tx_config: TransactionConfig,
+    rpc_type: RpcType,
+    prefix: Vec<Instruction>,
+    prefix_instructions: PrefixInstructions,
+    amm_buy: Option<AmmBuySkeleton>,
+}
+
+impl TransactionTemplate {
+    pub fn new(tx_config: TransactionConfig, rpc_type: RpcType) -> Self {
+        let mut prefix = build_prefix_instructions(&tx_config, &rpc_type);
+        // a durable nonce transaction starts with advancing the nonce, the account is picked per trade
+        if tx_config.durable_nonce {
+            let nonce_authority = tx_config.nonce_authority.pubkey();
+            prefix.insert(
+                0,
+                system_instruction::advance_nonce_account(&Pubkey::new_unique(), &nonce_authority),
+            );
+        }
+        let prefix_instructions = PrefixInstructions::locate(&prefix);
+        let amm_buy = AmmBuySkeleton::compile(&tx_config, &prefix, &prefix_instructions)
+            .map_err(|err| warn!("no dynamic AMM buy template, buys compile per trade: {:#}", err))
+            .ok();
+        TransactionTemplate {
+            tx_config,
+            rpc_type,
+            prefix,
+            prefix_instructions,
+            amm_buy,
+        }
+    }
+
+    // a fresh pick per transaction, spread over the provider's tip accounts
+    fn tip_account(&self) -> Option<Pubkey> {
+        self.prefix_instructions.tip.and_then(|_| self.tx_config.tip_accounts.pick(&self.rpc_type))
+    }
+
+    // bundle senders only: the tip transaction that follows the trade
+    pub fn tip_transaction(&self, recent_blockhash: Hash, fees: PriorityFees) -> Option<VersionedTransaction> {
+        if !self.tx_config.bundle {
+            return None;
+        }
+        let tip_account = self.tx_config.tip_accounts.pick(&self.rpc_type)?;
+        Some(build_tip_transaction(
+            &self.tx_config,
+            recent_blockhash,
+            &tip_account,
+            fees.tip,
+        ))
+    }
+
+    pub fn tx_config(&self) -> &TransactionConfig {
+        &self.tx_config
+    }
+
+    // With a durable nonce the nonce replaces the recent blockhash. Without one, as when every
+    // nonce account is in use, the advance instruction is dropped and the skeleton cannot serve.
+    pub fn transaction(
+        &self,
+        recent_blockhash: Hash,
+        trade: &Trade,
+        fees: PriorityFees,
+        nonce: Option<DurableNonce>,
+    ) -> anyhow::Result<VersionedTransaction> {
+        let skeleton = self.amm_buy.as_ref().filter(|_| self.prefix_instructions.nonce.is_none() || nonce.is_some());
+        match (trade, skeleton) {
+            (Trade::AmmBuy(accounts_for_buy), Some(skeleton)) => {
+                self.amm_buy_transaction(skeleton, recent_blockhash, accounts_for_buy, fees, nonce)
+            }
+            (Trade::AmmBuy(accounts_for_buy), None) => {
+                let prepared_instructions =
+                    PreparedInstructions::buy(&self.tx_config, BuyAccounts::Amm(**accounts_for_buy));
+                self.compile(recent_blockhash, &prepared_instructions, fees, nonce)
+            }
+            (Trade::Buy(buy_accounts), _) => {
+                let prepared_instructions = PreparedInstructions::buy(&self.tx_config, **buy_accounts);
+                self.compile(recent_blockhash, &prepared_instructions, fees, nonce)
+            }
+            (Trade::Prepared(prepared_instructions), _) => {
+                self.compile(recent_blockhash, prepared_instructions, fees, nonce)
+            }
+        }
+    }
+
+    fn amm_buy_transaction(
+        &self,
+        skeleton: &AmmBuySkeleton,
+        recent_blockhash: Hash,
+        accounts_for_buy: &AccountsForBuy,
+        fees: PriorityFees,
+        nonce: Option<DurableNonce>,
+    ) -> anyhow::Result<VersionedTransaction> {
+        let owner = self.tx_config.keypair.pubkey();
+        let user_token_account = get_associated_token_address(&owner, &accounts_for_buy.token_mint);
+
+        let mut message = skeleton.message.clone();
+        for (slot, index) in &skeleton.slots {
+            message.account_keys[*index] = slot.resolve(accounts_for_buy, &user_token_account);
+        }
+
+        let quote = accounts_for_buy.quote_buy(self.tx_config.buy_amount, self.tx_config.default_trade_fee_bps);
+        let min_amount_out = self.tx_config.min_amount_out(quote);
+        message.instructions[skeleton.swap_instruction].data[SWAP_MIN_OUT_OFFSET..SWAP_MIN_OUT_OFFSET + 8]
+            .copy_from_slice(&min_amount_out.to_le_bytes());
+        self.prefix_instructions.patch_message(&mut message, fees, self.tip_account(), nonce)?;
+        message.recent_blockhash = nonce.map_or(recent_blockhash, |nonce| nonce.nonce);
+
+        self.sign(message, nonce)
+    }
+
+    // the full path: prefix, compile against the lookup tables and sign
+    fn compile(
+        &self,
+        recent_blockhash: Hash,
+        prepared_instructions: &PreparedInstructions,
+        fees: PriorityFees,
+        nonce: Option<DurableNonce>,
+    ) -> anyhow::Result<VersionedTransaction> {
+        let mut instructions = self.prefix.clone();
+        self.prefix_instructions.patch_instructions(&mut instructions, fees, self.tip_account(), nonce);
+        if let (Some(index), None) = (self.prefix_instructions.nonce, nonce) {
+            instructions.remove(index);
+        }
+        instructions.extend(prepared_instructions.instructions.iter().cloned());
+        let message = Message::try_compile(
+            &self.tx_config.keypair.pubkey(),
+            &instructions,
+            &self.tx_config.lookup_tables,
+            nonce.map_or(recent_blockhash, |nonce| nonce.nonce),
+        )?;
+        self.sign(message, nonce)
+    }
+
+    // the nonce authority signs too when a nonce is advanced and it is not the trading wallet
+    fn sign(&self, message: Message, nonce: Option<DurableNonce>) -> anyhow::Result<VersionedTransaction> {
+        let keypair = self.tx_config.keypair.as_ref();
+        let nonce_authority = self.tx_config.nonce_authority.as_ref();
+        let message = VersionedMessage::V0(message);
+        match nonce.filter(|_| self.prefix_instructions.nonce.is_some()) {
+            Some(_) if nonce_authority.pubkey() != keypair.pubkey() => {
+                Ok(VersionedTransaction::try_new(message, &[keypair, nonce_authority])?)
+            }
+            _ => Ok(VersionedTransaction::try_new(message, &[keypair])?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PingThingsArgs;
+    use crate::tx_senders::constants::JITO_TIP_ACCOUNTS;
+    use crate::tx_senders::transaction::build_transaction_with_config;
+    use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+    use solana_sdk::signature::Keypair;
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    fn tx_config() -> TransactionConfig {
+        let config: PingThingsArgs = serde_yaml::from_str(&format!(
+            "rpc: {{}}
+geyser_url: ''
+geyser_x_token: ''
+private_key: {}
+compute_unit_price: 100000
+compute_unit_limit: 100000
+tip: 0.001
+buy_amount: 0.01",
+            Keypair::new().to_base58_string()
+        ))
+        .unwrap();
+        config.into()
+    }
+
+    fn accounts_for_buy() -> AccountsForBuy {
+        AccountsForBuy {
+            pool: Pubkey::new_unique(),
+            token_mint: Pubkey::new_unique(),
+            quote_side: QuoteSide::B,
+            a_vault: Pubkey::new_unique(),
+            b_vault: Pubkey::new_unique(),
+            a_token_vault: Pubkey::new_unique(),
+            b_token_vault: Pubkey::new_unique(),
+            a_vault_lp_mint: Pubkey::new_unique(),
+            b_vault_lp_mint: Pubkey::new_unique(),
+            a_vault_lp: Pubkey::new_unique(),
+            b_vault_lp: Pubkey::new_unique(),
+            protocol_token_a_fee: Pubkey::new_unique(),
+            protocol_token_b_fee: Pubkey::new_unique(),
+            token_a_amount: 1_000_000_000_000_000,
+            token_b_amount: 10 * LAMPORTS_PER_SOL,
+            trade_fee_bps: Some(25),
+        }
+    }
+
+    #[test]
+    fn tip_account_in_lookup_table() {
+        let mut tx_config = tx_config();
+        tx_config.lookup_tables = vec![AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: JITO_TIP_ACCOUNTS.to_vec(),
+        }];
+        let template = TransactionTemplate::new(tx_config.clone(), RpcType::Jito);
+
+        // the tip account cannot be patched, the buy compiles per trade instead of going out with a stale tip account
+        assert!(template.amm_buy.is_none());
+        let trade = Trade::AmmBuy(Box::new(accounts_for_buy()));
+        assert!(template.transaction(Hash::new_unique(), &trade, tx_config.static_fees(), None).is_ok());
+
+        let prepared_instructions = PreparedInstructions::buy(&tx_config, BuyAccounts::Amm(accounts_for_buy()));
+        let mut instructions = template.prefix.clone();
+        instructions.extend(prepared_instructions.instructions);
+        let mut message = Message::try_compile(
+            &tx_config.keypair.pubkey(),
+            &instructions,
+            &tx_config.lookup_tables,
+            Hash::default(),
+        )
+        .unwrap();
+        let result = template.prefix_instructions.patch_message(
+            &mut message,
+            tx_config.static_fees(),
+            Some(JITO_TIP_ACCOUNTS[0]),
+            None,
+        );
+        assert!(result.is_err());
+    }
+
+    // Times a dynamic AMM buy for every sender type, compiled from scratch as before
+    // templates and through the templates. Measure optimized:
+    // cargo test --release bench_templates -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_templates() {
+        const ITERATIONS: u32 = 1_000;
+        let tx_config = tx_config();
+        let rpc_types = [
+            RpcType::SolanaRpc,
+            RpcType::Jito,
+            RpcType::Bloxroute,
+            RpcType::Nextblock,
+        ];
+        let templates: Vec<TransactionTemplate> =
+            rpc_types.iter().map(|rpc_type| TransactionTemplate::new(tx_config.clone(), rpc_type.clone())).collect();
+        let accounts_for_buy = accounts_for_buy();
+        let recent_blockhash = Hash::new_unique();
+        let trade = Trade::AmmBuy(Box::new(accounts_for_buy));
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            for template in &templates {
+                let prepared_instructions =
+                    PreparedInstructions::buy(&template.tx_config, BuyAccounts::Amm(accounts_for_buy));
+                black_box(build_transaction_with_config(
+                    &template.tx_config,
+                    &template.rpc_type,
+                    recent_blockhash,
+                    &prepared_instructions,
+                ));
+            }
+        }
+        let from_scratch = start.elapsed() / ITERATIONS;
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            for template in &templates {
+                black_box(template.transaction(recent_blockhash, &trade, tx_config.static_fees(), None).unwrap());
+            }
+        }
+        let templated = start.elapsed() / ITERATIONS;
+
+        println!(
+            "{} senders: from scratch {:?}, templates {:?} per detection ({:.1}x)",
+            templates.len(),
+            from_scratch,
+            templated,
+            from_scratch.as_secs_f64() / templated.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use crate::config::{RpcType, TipAccountSelection, TipAccountsConfig};
+use crate::tx_senders::constants::{BLOXROUTE_TIP, JITO_TIP_ACCOUNTS, NEXTBLOCK_TIP};
+use rand::Rng;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// the tip accounts of one provider and the round-robin position
+#[derive(Debug)]
+struct ProviderTipAccounts {
+    accounts: Vec<Pubkey>,
+    next: AtomicUsize,
+}
+
+impl ProviderTipAccounts {
+    // an empty list from the config keeps the built-in accounts
+    fn new(configured: &[String], defaults: &[Pubkey]) -> Self {
+        let accounts = match configured.is_empty() {
+            true => defaults.to_vec(),
+            false => configured
+                .iter()
+                .map(|account| Pubkey::from_str(account).expect("invalid pubkey in tip_accounts config"))
+                .collect(),
+        };
+        ProviderTipAccounts {
+            accounts,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    fn pick(&self, selection: TipAccountSelection) -> Pubkey {
+        let index = match selection {
+            TipAccountSelection::Random => rand::thread_rng().gen_range(0..self.accounts.len()),
+            TipAccountSelection::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % self.accounts.len(),
+        };
+        self.accounts[index]
+    }
+}
+
+// Tip accounts per block engine provider. Spreading tips over all of a provider's accounts
+// avoids contending for the write lock of a single one.
+#[derive(Debug)]
+pub struct TipAccounts {
+    selection: TipAccountSelection,
+    jito: ProviderTipAccounts,
+    bloxroute: ProviderTipAccounts,
+    nextblock: ProviderTipAccounts,
+}
+
+impl TipAccounts {
+    pub fn new(config: &TipAccountsConfig) -> Self {
+        TipAccounts {
+            selection: config.selection,
+            jito: ProviderTipAccounts::new(&config.jito, &JITO_TIP_ACCOUNTS),
+            bloxroute: ProviderTipAccounts::new(&config.bloxroute, &[BLOXROUTE_TIP]),
+            nextblock: ProviderTipAccounts::new(&config.nextblock, &[NEXTBLOCK_TIP]),
+        }
+    }
+
+    // None for senders that take no tip
+    pub fn pick(&self, rpc_type: &RpcType) -> Option<Pubkey> {
+        let provider = match rpc_type {
+            RpcType::Jito => &self.jito,
+            RpcType::Bloxroute => &self.bloxroute,
+            RpcType::Nextblock => &self.nextblock,
+            RpcType::SolanaRpc => return None,
+        };
+        Some(provider.pick(self.selection))
+    }
+
+    pub fn contains(&self, account: &Pubkey) -> bool {
+        [&self.jito, &self.bloxroute, &self.nextblock].iter().any(|provider| provider.accounts.contains(account))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tip_accounts(selection: TipAccountSelection, jito: &[Pubkey]) -> TipAccounts {
+        TipAccounts::new(&TipAccountsConfig {
+            selection,
+            jito: jito.iter().map(Pubkey::to_string).collect(),
+            ..TipAccountsConfig::default()
+        })
+    }
+
+    #[test]
+    fn round_robin_cycles_through_the_accounts() {
+        let accounts = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let tip_accounts = tip_accounts(TipAccountSelection::RoundRobin, &accounts);
+        let picked: Vec<Pubkey> = (0..7).map(|_| tip_accounts.pick(&RpcType::Jito).unwrap()).collect();
+        assert_eq!(
+            picked,
+            [accounts.as_slice(), accounts.as_slice(), &accounts[..1]].concat()
+        );
+    }
+
+    #[test]
+    fn providers_keep_their_own_position() {
+        let accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let tip_accounts = tip_accounts(TipAccountSelection::RoundRobin, &accounts);
+        assert_eq!(tip_accounts.pick(&RpcType::Jito), Some(accounts[0]));
+        assert_eq!(tip_accounts.pick(&RpcType::Bloxroute), Some(BLOXROUTE_TIP));
+        assert_eq!(tip_accounts.pick(&RpcType::Nextblock), Some(NEXTBLOCK_TIP));
+        assert_eq!(tip_accounts.pick(&RpcType::Jito), Some(accounts[1]));
+        assert_eq!(tip_accounts.pick(&RpcType::SolanaRpc), None);
+    }
+
+    #[test]
+    fn random_picks_a_configured_account() {
+        let accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let tip_accounts = tip_accounts(TipAccountSelection::Random, &accounts);
+        for _ in 0..20 {
+            assert!(accounts.contains(&tip_accounts.pick(&RpcType::Jito).unwrap()));
+        }
+    }
+
+    #[test]
+    fn empty_list_keeps_the_known_accounts() {
+        let tip_accounts = tip_accounts(TipAccountSelection::RoundRobin, &[]);
+        let picked: Vec<Pubkey> =
+            (0..JITO_TIP_ACCOUNTS.len()).map(|_| tip_accounts.pick(&RpcType::Jito).unwrap()).collect();
+        assert_eq!(picked, JITO_TIP_ACCOUNTS);
+        assert!(tip_accounts.contains(&JITO_TIP_ACCOUNTS[0]));
+        assert!(!tip_accounts.contains(&Pubkey::new_unique()));
+    }
+}
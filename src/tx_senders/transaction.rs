@@ -1,55 +1,215 @@
-use crate::config::{PingThingsArgs, RpcType};
-use crate::meteora::AccountsForBuy;
-use crate::tx_senders::constants::{JITO_TIP, TOKEN_PROGRAM};
+use crate::config::{PingThingsArgs, RpcConfig, RpcType, WsolMode};
+use crate::damm_v2::{DAMM_V2_SWAP_IX_DISC, DammV2AccountsForBuy};
+use crate::dlmm::{DLMM_SWAP_IX_DISC, DlmmAccountsForBuy};
+use crate::meteora::instructions::SWAP_DISC;
+use crate::meteora::{AccountsForBuy, AmmSwap, BPS_DENOMINATOR, QuoteSide};
+use crate::priority_fee::PriorityFees;
+use crate::tx_senders::constants::TOKEN_PROGRAM;
+use crate::tx_senders::tip_accounts::TipAccounts;
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
 use solana_sdk::compute_budget::ComputeBudgetInstruction;
 use solana_sdk::hash::Hash;
 use solana_sdk::instruction::{AccountMeta, Instruction};
 use solana_sdk::message::VersionedMessage;
 use solana_sdk::message::v0::Message;
 use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{Keypair, Signer};
 use solana_sdk::system_instruction;
 use solana_sdk::transaction::VersionedTransaction;
-use spl_associated_token_account::get_associated_token_address;
-use spl_associated_token_account::instruction::create_associated_token_account;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_associated_token_account::{get_associated_token_address, get_associated_token_address_with_program_id};
 use std::sync::Arc;
 
-use super::constants::{BLOXROUTE_TIP, METEORA_POOLS_PROGRAM, METEORA_VAULT_PROGRAM, NEXTBLOCK_TIP, WSOL_MINT};
+use super::constants::{
+    METEORA_DAMM_V2_EVENT_AUTHORITY, METEORA_DAMM_V2_PROGRAM, METEORA_DLMM_EVENT_AUTHORITY, METEORA_DLMM_PROGRAM,
+    METEORA_POOLS_PROGRAM, METEORA_VAULT_PROGRAM, WSOL_MINT,
+};
+
+// pool accounts of a buy, per pool program
+#[derive(Debug, Copy, Clone)]
+pub enum BuyAccounts {
+    Amm(AccountsForBuy),
+    // mirrored swap with its own size, see copy trading
+    AmmSwap(AmmSwap),
+    Dlmm(DlmmAccountsForBuy),
+    DammV2(DammV2AccountsForBuy),
+}
+
+// A token to WSOL swap. The token account can only be closed when the whole balance is sold,
+// unwrapping closes the WSOL account and leaves nothing to buy with until it is funded again.
+#[derive(Debug, Copy, Clone)]
+pub struct SellOrder {
+    pub swap: AmmSwap,
+    pub close_token_account: bool,
+    pub unwrap_wsol: bool,
+}
+
+// Instructions of a trade, compute budget and tip are added by each sender.
+#[derive(Debug, Clone)]
+pub struct PreparedInstructions {
+    pub instructions: Vec<Instruction>,
+}
+
+impl PreparedInstructions {
+    pub fn buy(tx_config: &TransactionConfig, buy_accounts: BuyAccounts) -> Self {
+        let swap_instructions = match buy_accounts {
+            BuyAccounts::Amm(accounts_for_buy) => build_amm_buy_instructions(tx_config, accounts_for_buy),
+            BuyAccounts::AmmSwap(swap) => build_amm_swap_instructions(tx_config, swap),
+            BuyAccounts::Dlmm(accounts_for_buy) => build_dlmm_buy_instructions(tx_config, accounts_for_buy),
+            BuyAccounts::DammV2(accounts_for_buy) => build_damm_v2_buy_instructions(tx_config, accounts_for_buy),
+        };
+
+        // WSOL spent by the swap, mirrored swaps can go either way
+        let wsol_amount = match buy_accounts {
+            BuyAccounts::AmmSwap(swap) if swap.input_mint != WSOL_MINT => None,
+            BuyAccounts::AmmSwap(swap) => Some(swap.amount_in),
+            _ => Some(tx_config.buy_amount),
+        };
+
+        let instructions = match (tx_config.wsol_mode, wsol_amount) {
+            (WsolMode::AutoWrap, Some(wsol_amount)) => {
+                let owner = tx_config.keypair.pubkey();
+                let mut instructions = build_wrap_wsol_instructions(&owner, wsol_amount);
+                instructions.extend(swap_instructions);
+                instructions.push(build_close_token_account_instruction(&owner, &WSOL_MINT));
+                instructions
+            }
+            _ => swap_instructions,
+        };
+        PreparedInstructions { instructions }
+    }
+
+    pub fn sell(tx_config: &TransactionConfig, sell_order: SellOrder) -> Self {
+        PreparedInstructions {
+            instructions: build_amm_sell_instructions(tx_config, sell_order),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct TransactionConfig {
+    // the trading wallet, pays and owns the token accounts
     pub keypair: Arc<Keypair>,
+    // the private_key wallet, authority of the nonce accounts
+    pub nonce_authority: Arc<Keypair>,
     pub compute_unit_limit: u32,
     pub compute_unit_price: u64,
     pub tip: u64,
+    // follow the priority fee oracle: the instruction is always set, then patched per trade
+    pub dynamic_compute_unit_price: bool,
+    pub dynamic_tip: bool,
+    // shared by every sender, round-robin positions are per provider
+    pub tip_accounts: Arc<TipAccounts>,
+    // the tip goes in its own transaction, bundled after the trade
+    pub bundle: bool,
+    // trades advance a nonce account first and sign its nonce instead of a recent blockhash
+    pub durable_nonce: bool,
     pub buy_amount: u64,
-    pub min_amount_out: u64,
+    pub slippage_bps: u64,
+    pub default_trade_fee_bps: u64,
+    pub min_amount_out_floor: u64,
+    pub wsol_mode: WsolMode,
+    // loaded at startup, see lookup_tables
+    pub lookup_tables: Vec<AddressLookupTableAccount>,
+}
+
+impl TransactionConfig {
+    pub fn static_fees(&self) -> PriorityFees {
+        PriorityFees {
+            compute_unit_price: self.compute_unit_price,
+            tip: self.tip,
+        }
+    }
+
+    // what this sender pays, the oracle's fees unless the sender pins its own
+    pub fn fees(&self, oracle_fees: Option<PriorityFees>) -> PriorityFees {
+        let Some(oracle_fees) = oracle_fees else {
+            return self.static_fees();
+        };
+        PriorityFees {
+            compute_unit_price: match self.dynamic_compute_unit_price {
+                true => oracle_fees.compute_unit_price,
+                false => self.compute_unit_price,
+            },
+            tip: match self.dynamic_tip {
+                true => oracle_fees.tip,
+                false => self.tip,
+            },
+        }
+    }
+
+    pub fn for_wallet(&self, keypair: Arc<Keypair>) -> Self {
+        TransactionConfig {
+            keypair,
+            ..self.clone()
+        }
+    }
+
+    // the global config with the sender's overrides, an overridden fee no longer follows the oracle
+    pub fn for_sender(&self, rpc: &RpcConfig) -> Self {
+        let mut tx_config = self.clone();
+        if let Some(tip) = rpc.tip {
+            tx_config.tip = (tip * LAMPORTS_PER_SOL as f64) as u64;
+            tx_config.dynamic_tip = false;
+        }
+        if let Some(compute_unit_price) = rpc.compute_unit_price {
+            tx_config.compute_unit_price = compute_unit_price;
+            tx_config.dynamic_compute_unit_price = false;
+        }
+        if let Some(compute_unit_limit) = rpc.compute_unit_limit {
+            tx_config.compute_unit_limit = compute_unit_limit;
+        }
+        if let Some(buy_amount) = rpc.buy_amount {
+            tx_config.buy_amount = (buy_amount * LAMPORTS_PER_SOL as f64) as u64;
+        }
+        tx_config.bundle = rpc.bundle && matches!(rpc.rpc_type, RpcType::Jito);
+        tx_config
+    }
+
+    // pool quote minus slippage, never below the static floor
+    pub fn min_amount_out(&self, quote: u64) -> u64 {
+        self.apply_slippage(quote).max(self.min_amount_out_floor)
+    }
+
+    pub fn apply_slippage(&self, quote: u64) -> u64 {
+        let quote = quote as u128;
+        let min_amount_out =
+            quote * (BPS_DENOMINATOR - self.slippage_bps.min(BPS_DENOMINATOR)) as u128 / BPS_DENOMINATOR as u128;
+        min_amount_out as u64
+    }
 }
 
 impl From<PingThingsArgs> for TransactionConfig {
     fn from(args: PingThingsArgs) -> Self {
-        let keypair = Keypair::from_base58_string(args.private_key.as_str());
+        let keypair = Arc::new(Keypair::from_base58_string(args.private_key.as_str()));
 
         let tip: u64 = (args.tip * LAMPORTS_PER_SOL as f64) as u64;
         let buy_amount: u64 = (args.buy_amount * LAMPORTS_PER_SOL as f64) as u64;
-        let min_amount_out: u64 = (args.min_amount_out * 1_000_000 as f64) as u64;
+        let min_amount_out_floor: u64 = (args.min_amount_out * 1_000_000f64) as u64;
 
         TransactionConfig {
-            keypair: Arc::new(keypair),
+            keypair: keypair.clone(),
+            nonce_authority: keypair,
             compute_unit_limit: args.compute_unit_limit,
             compute_unit_price: args.compute_unit_price,
             tip,
+            dynamic_compute_unit_price: args.priority_fee.enabled,
+            dynamic_tip: args.priority_fee.enabled,
+            tip_accounts: Arc::new(TipAccounts::new(&args.tip_accounts)),
+            bundle: false,
+            durable_nonce: !args.nonce_accounts.is_empty(),
             buy_amount,
-            min_amount_out,
+            slippage_bps: args.slippage_bps,
+            default_trade_fee_bps: args.default_trade_fee_bps,
+            min_amount_out_floor,
+            wsol_mode: args.wsol_mode.unwrap_or_default(),
+            lookup_tables: Vec::new(),
         }
     }
 }
-pub fn build_transaction_with_config(
-    tx_config: &TransactionConfig,
-    rpc_type: &RpcType,
-    recent_blockhash: Hash,
-    accounts_for_buy: AccountsForBuy,
-) -> VersionedTransaction {
+// compute budget and the tip of the sender, ahead of the trade instructions
+pub fn build_prefix_instructions(tx_config: &TransactionConfig, rpc_type: &RpcType) -> Vec<Instruction> {
     let mut instructions = Vec::new();
 
     if tx_config.compute_unit_limit > 0 {
@@ -57,39 +217,70 @@ pub fn build_transaction_with_config(
         instructions.push(compute_unit_limit);
     }
 
-    if tx_config.compute_unit_price > 0 {
+    if tx_config.compute_unit_price > 0 || tx_config.dynamic_compute_unit_price {
         let compute_unit_price = ComputeBudgetInstruction::set_compute_unit_price(tx_config.compute_unit_price);
         instructions.push(compute_unit_price);
     }
 
-    if tx_config.tip > 0 {
-        let tip_instruction: Option<Instruction> = match rpc_type {
-            RpcType::Jito => Some(system_instruction::transfer(
-                &tx_config.keypair.pubkey(),
-                &JITO_TIP,
-                tx_config.tip,
-            )),
-            RpcType::Bloxroute => Some(system_instruction::transfer(
-                &tx_config.keypair.pubkey(),
-                &BLOXROUTE_TIP,
-                tx_config.tip,
-            )),
-            RpcType::Nextblock => Some(system_instruction::transfer(
-                &tx_config.keypair.pubkey(),
-                &NEXTBLOCK_TIP,
-                tx_config.tip,
-            )),
-            _ => None,
-        };
+    if (tx_config.tip > 0 || tx_config.dynamic_tip) && !tx_config.bundle {
+        let tip_instruction = tx_config
+            .tip_accounts
+            .pick(rpc_type)
+            .map(|tip_account| system_instruction::transfer(&tx_config.keypair.pubkey(), &tip_account, tx_config.tip));
 
-        if tip_instruction.is_some() {
-            instructions.push(tip_instruction.unwrap());
+        if let Some(tip_instruction) = tip_instruction {
+            instructions.push(tip_instruction);
         }
     }
 
+    instructions
+}
+
+// the tip of a bundle, signed on its own
+pub fn build_tip_transaction(
+    tx_config: &TransactionConfig,
+    recent_blockhash: Hash,
+    tip_account: &Pubkey,
+    tip: u64,
+) -> VersionedTransaction {
+    let owner = tx_config.keypair.pubkey();
+    let tip_instruction = system_instruction::transfer(&owner, tip_account, tip);
+    let message = Message::try_compile(&owner, &[tip_instruction], &[], recent_blockhash).unwrap();
+    VersionedTransaction::try_new(VersionedMessage::V0(message), &[&tx_config.keypair]).unwrap()
+}
+
+// what every sender did before templates, the template benchmark compares against it
+#[cfg(test)]
+pub fn build_transaction_with_config(
+    tx_config: &TransactionConfig,
+    rpc_type: &RpcType,
+    recent_blockhash: Hash,
+    prepared_instructions: &PreparedInstructions,
+) -> VersionedTransaction {
+    let mut instructions = build_prefix_instructions(tx_config, rpc_type);
+
+    let owner = tx_config.keypair.pubkey();
+
+    instructions.extend(prepared_instructions.instructions.iter().cloned());
+
+    let message_v0 = Message::try_compile(
+        &owner,
+        instructions.as_slice(),
+        &tx_config.lookup_tables,
+        recent_blockhash,
+    )
+    .unwrap();
+
+    let versioned_message = VersionedMessage::V0(message_v0);
+
+    VersionedTransaction::try_new(versioned_message, &[&tx_config.keypair]).unwrap()
+}
+
+fn build_amm_buy_instructions(tx_config: &TransactionConfig, accounts_for_buy: AccountsForBuy) -> Vec<Instruction> {
     let AccountsForBuy {
         pool,
-        a_token_mint,
+        token_mint,
+        quote_side,
         a_vault,
         b_vault,
         a_token_vault,
@@ -98,20 +289,30 @@ pub fn build_transaction_with_config(
         b_vault_lp_mint,
         a_vault_lp,
         b_vault_lp,
-        protocol_token_fee,
+        protocol_token_a_fee,
+        protocol_token_b_fee,
+        ..
     } = accounts_for_buy;
 
     let owner = tx_config.keypair.pubkey();
     let user_source_token = get_associated_token_address(&owner, &WSOL_MINT);
-    let user_destination_token = get_associated_token_address(&owner, &a_token_mint);
-    let token_account_instruction = create_associated_token_account(&owner, &owner, &a_token_mint, &TOKEN_PROGRAM);
+    let user_destination_token = get_associated_token_address(&owner, &token_mint);
+    let token_account_instruction =
+        create_associated_token_account_idempotent(&owner, &owner, &token_mint, &TOKEN_PROGRAM);
 
-    instructions.push(token_account_instruction);
+    let mut instructions = vec![token_account_instruction];
 
-    // swap
-    let mut data = vec![0xf8, 0xc6, 0x9e, 0x91, 0xe1, 0x75, 0x87, 0xc8];
+    // swap: the direction follows the source token account, vaults stay in pool order
+    // and the protocol fee is charged on the input (WSOL) side
+    let protocol_token_fee = match quote_side {
+        QuoteSide::A => protocol_token_a_fee,
+        QuoteSide::B => protocol_token_b_fee,
+    };
+
+    let mut data = SWAP_DISC.to_vec();
     data.extend_from_slice(&tx_config.buy_amount.to_le_bytes());
-    data.extend_from_slice(&tx_config.min_amount_out.to_le_bytes());
+    let quote = accounts_for_buy.quote_buy(tx_config.buy_amount, tx_config.default_trade_fee_bps);
+    data.extend_from_slice(&tx_config.min_amount_out(quote).to_le_bytes());
 
     let accounts = vec![
         AccountMeta::new(pool, false),
@@ -139,9 +340,249 @@ pub fn build_transaction_with_config(
 
     instructions.push(swap_instruction);
 
-    let message_v0 = Message::try_compile(&owner, instructions.as_slice(), &[], recent_blockhash).unwrap();
+    instructions
+}
 
-    let versioned_message = VersionedMessage::V0(message_v0);
+// the output account may already exist, so it is created idempotently
+fn build_amm_swap_instructions(tx_config: &TransactionConfig, swap: AmmSwap) -> Vec<Instruction> {
+    let owner = tx_config.keypair.pubkey();
+    let user_source_token = get_associated_token_address(&owner, &swap.input_mint);
+    let user_destination_token = get_associated_token_address(&owner, &swap.output_mint);
+    let token_account_instruction =
+        create_associated_token_account_idempotent(&owner, &owner, &swap.output_mint, &TOKEN_PROGRAM);
 
-    VersionedTransaction::try_new(versioned_message, &[&tx_config.keypair]).unwrap()
+    let mut data = SWAP_DISC.to_vec();
+    data.extend_from_slice(&swap.amount_in.to_le_bytes());
+    data.extend_from_slice(&tx_config.apply_slippage(swap.expected_out).to_le_bytes());
+
+    let accounts = vec![
+        AccountMeta::new(swap.pool, false),
+        AccountMeta::new(user_source_token, false),
+        AccountMeta::new(user_destination_token, false),
+        AccountMeta::new(swap.a_vault, false),
+        AccountMeta::new(swap.b_vault, false),
+        AccountMeta::new(swap.a_token_vault, false),
+        AccountMeta::new(swap.b_token_vault, false),
+        AccountMeta::new(swap.a_vault_lp_mint, false),
+        AccountMeta::new(swap.b_vault_lp_mint, false),
+        AccountMeta::new(swap.a_vault_lp, false),
+        AccountMeta::new(swap.b_vault_lp, false),
+        AccountMeta::new(swap.protocol_token_fee, false),
+        AccountMeta::new_readonly(owner, true),
+        AccountMeta::new_readonly(METEORA_VAULT_PROGRAM, false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM, false),
+    ];
+
+    let swap_instruction = Instruction {
+        program_id: METEORA_POOLS_PROGRAM,
+        accounts,
+        data,
+    };
+
+    vec![token_account_instruction, swap_instruction]
+}
+
+fn build_amm_sell_instructions(tx_config: &TransactionConfig, sell_order: SellOrder) -> Vec<Instruction> {
+    let SellOrder {
+        swap,
+        close_token_account,
+        unwrap_wsol,
+    } = sell_order;
+
+    let owner = tx_config.keypair.pubkey();
+    let mut instructions = build_amm_swap_instructions(tx_config, swap);
+
+    if close_token_account {
+        instructions.push(build_close_token_account_instruction(&owner, &swap.input_mint));
+    }
+
+    // closing the WSOL account returns its lamports, the proceeds included
+    if unwrap_wsol {
+        instructions.push(build_close_token_account_instruction(&owner, &swap.output_mint));
+    }
+
+    instructions
+}
+
+// funds the WSOL account with `amount` lamports, creating it when missing
+fn build_wrap_wsol_instructions(owner: &Pubkey, amount: u64) -> Vec<Instruction> {
+    let wsol_account = get_associated_token_address(owner, &WSOL_MINT);
+    vec![
+        create_associated_token_account_idempotent(owner, owner, &WSOL_MINT, &TOKEN_PROGRAM),
+        system_instruction::transfer(owner, &wsol_account, amount),
+        spl_token::instruction::sync_native(&TOKEN_PROGRAM, &wsol_account).unwrap(),
+    ]
+}
+
+// the owner gets the rent back, and the balance too for WSOL
+fn build_close_token_account_instruction(owner: &Pubkey, mint: &Pubkey) -> Instruction {
+    let token_account = get_associated_token_address(owner, mint);
+    spl_token::instruction::close_account(&TOKEN_PROGRAM, &token_account, owner, owner, &[]).unwrap()
+}
+
+fn build_dlmm_buy_instructions(
+    tx_config: &TransactionConfig,
+    accounts_for_buy: DlmmAccountsForBuy,
+) -> Vec<Instruction> {
+    let DlmmAccountsForBuy {
+        lb_pair,
+        token_mint,
+        token_x_mint,
+        token_y_mint,
+        reserve_x,
+        reserve_y,
+        oracle,
+        token_program,
+        bin_array_bitmap_extension,
+        bin_arrays,
+        ..
+    } = accounts_for_buy;
+
+    let owner = tx_config.keypair.pubkey();
+    let user_token_in = get_associated_token_address(&owner, &WSOL_MINT);
+    let user_token_out = get_associated_token_address(&owner, &token_mint);
+    let token_account_instruction =
+        create_associated_token_account_idempotent(&owner, &owner, &token_mint, &token_program);
+
+    let mut data = DLMM_SWAP_IX_DISC.to_vec();
+    data.extend_from_slice(&tx_config.buy_amount.to_le_bytes());
+    let quote = accounts_for_buy.quote_buy(tx_config.buy_amount, tx_config.default_trade_fee_bps);
+    data.extend_from_slice(&tx_config.min_amount_out(quote).to_le_bytes());
+
+    // optional accounts are passed as the program id when absent
+    let mut accounts = vec![
+        AccountMeta::new(lb_pair, false),
+        AccountMeta::new_readonly(bin_array_bitmap_extension.unwrap_or(METEORA_DLMM_PROGRAM), false),
+        AccountMeta::new(reserve_x, false),
+        AccountMeta::new(reserve_y, false),
+        AccountMeta::new(user_token_in, false),
+        AccountMeta::new(user_token_out, false),
+        AccountMeta::new_readonly(token_x_mint, false),
+        AccountMeta::new_readonly(token_y_mint, false),
+        AccountMeta::new(oracle, false),
+        AccountMeta::new_readonly(METEORA_DLMM_PROGRAM, false),
+        AccountMeta::new_readonly(owner, true),
+        AccountMeta::new_readonly(token_program, false),
+        AccountMeta::new_readonly(token_program, false),
+        AccountMeta::new_readonly(METEORA_DLMM_EVENT_AUTHORITY, false),
+        AccountMeta::new_readonly(METEORA_DLMM_PROGRAM, false),
+    ];
+
+    // bin arrays the swap walks through, starting at the active one
+    accounts.extend(bin_arrays.iter().map(|bin_array| AccountMeta::new(*bin_array, false)));
+
+    let swap_instruction = Instruction {
+        program_id: METEORA_DLMM_PROGRAM,
+        accounts,
+        data,
+    };
+
+    vec![token_account_instruction, swap_instruction]
+}
+
+fn build_damm_v2_buy_instructions(
+    tx_config: &TransactionConfig,
+    accounts_for_buy: DammV2AccountsForBuy,
+) -> Vec<Instruction> {
+    let DammV2AccountsForBuy {
+        pool,
+        pool_authority,
+        token_mint,
+        token_a_mint,
+        token_b_mint,
+        token_a_vault,
+        token_b_vault,
+        token_a_program,
+        token_b_program,
+        ..
+    } = accounts_for_buy;
+
+    let owner = tx_config.keypair.pubkey();
+    let token_program = accounts_for_buy.token_program();
+    let input_token_account = get_associated_token_address(&owner, &WSOL_MINT);
+    let output_token_account = get_associated_token_address_with_program_id(&owner, &token_mint, &token_program);
+    let token_account_instruction =
+        create_associated_token_account_idempotent(&owner, &owner, &token_mint, &token_program);
+
+    let mut data = DAMM_V2_SWAP_IX_DISC.to_vec();
+    data.extend_from_slice(&tx_config.buy_amount.to_le_bytes());
+    let quote = accounts_for_buy.quote_buy(tx_config.buy_amount, tx_config.default_trade_fee_bps);
+    data.extend_from_slice(&tx_config.min_amount_out(quote).to_le_bytes());
+
+    // the optional referral account is passed as the program id
+    let accounts = vec![
+        AccountMeta::new_readonly(pool_authority, false),
+        AccountMeta::new(pool, false),
+        AccountMeta::new(input_token_account, false),
+        AccountMeta::new(output_token_account, false),
+        AccountMeta::new(token_a_vault, false),
+        AccountMeta::new(token_b_vault, false),
+        AccountMeta::new_readonly(token_a_mint, false),
+        AccountMeta::new_readonly(token_b_mint, false),
+        AccountMeta::new_readonly(owner, true),
+        AccountMeta::new_readonly(token_a_program, false),
+        AccountMeta::new_readonly(token_b_program, false),
+        AccountMeta::new_readonly(METEORA_DAMM_V2_PROGRAM, false),
+        AccountMeta::new_readonly(METEORA_DAMM_V2_EVENT_AUTHORITY, false),
+        AccountMeta::new_readonly(METEORA_DAMM_V2_PROGRAM, false),
+    ];
+
+    let swap_instruction = Instruction {
+        program_id: METEORA_DAMM_V2_PROGRAM,
+        accounts,
+        data,
+    };
+
+    vec![token_account_instruction, swap_instruction]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx_config(slippage_bps: u64, min_amount_out: f64) -> TransactionConfig {
+        let config: PingThingsArgs = serde_yaml::from_str(&format!(
+            "rpc: {{}}
+geyser_url: ''
+geyser_x_token: ''
+private_key: {}
+compute_unit_price: 100000
+compute_unit_limit: 100000
+tip: 0.001
+buy_amount: 0.01
+slippage_bps: {}
+min_amount_out: {}",
+            Keypair::new().to_base58_string(),
+            slippage_bps,
+            min_amount_out
+        ))
+        .unwrap();
+        config.into()
+    }
+
+    #[test]
+    fn slippage_comes_off_the_quote() {
+        let tx_config = tx_config(1_500, 0.0);
+        assert_eq!(tx_config.apply_slippage(1_000_000), 850_000);
+        assert_eq!(tx_config.min_amount_out(1_000_000), 850_000);
+        assert_eq!(
+            tx_config.apply_slippage(u64::MAX),
+            (u64::MAX as u128 * 8_500 / 10_000) as u64
+        );
+    }
+
+    #[test]
+    fn slippage_is_capped_at_the_whole_quote() {
+        assert_eq!(tx_config(20_000, 0.0).apply_slippage(1_000_000), 0);
+        assert_eq!(tx_config(0, 0.0).apply_slippage(1_000_000), 1_000_000);
+    }
+
+    #[test]
+    fn static_floor_is_in_6_decimal_units() {
+        let tx_config = tx_config(1_500, 2.5);
+        assert_eq!(tx_config.min_amount_out_floor, 2_500_000);
+        // an absurd quote still never goes below the floor
+        assert_eq!(tx_config.min_amount_out(1_000), 2_500_000);
+        assert_eq!(tx_config.min_amount_out(10_000_000), 8_500_000);
+    }
 }
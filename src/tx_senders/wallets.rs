@@ -0,0 +1,98 @@
+use crate::config::{PingThingsArgs, WalletSelection, WalletsConfig};
+use crate::tx_senders::TxSender;
+use crate::tx_senders::template::TransactionTemplate;
+use crate::tx_senders::transaction::TransactionConfig;
+use anyhow::{Context, bail};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::signer::keypair::read_keypair_file;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::info;
+
+// A trading wallet with its own template for every sender, templates bake the owner in.
+pub struct Wallet {
+    // before the senders' overrides
+    pub tx_config: TransactionConfig,
+    pub rpcs: Vec<(Arc<dyn TxSender>, Arc<TransactionTemplate>)>,
+}
+
+impl Wallet {
+    pub fn pubkey(&self) -> Pubkey {
+        self.tx_config.keypair.pubkey()
+    }
+}
+
+// The wallets trades rotate over, the next per_trade of them take each trade.
+#[derive(Clone)]
+pub struct WalletPool {
+    wallets: Vec<Arc<Wallet>>,
+    per_trade: usize,
+    // shared by the clones, every controller holds one
+    next: Arc<AtomicUsize>,
+}
+
+impl WalletPool {
+    pub fn new(wallets: Vec<Arc<Wallet>>, per_trade: usize) -> Self {
+        WalletPool {
+            wallets,
+            per_trade,
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // wallets sharing a trade, each one's buy_amount is its share
+    pub fn per_trade(config: &WalletsConfig, wallet_count: usize) -> usize {
+        match config.selection {
+            WalletSelection::RoundRobin => 1,
+            WalletSelection::Split => config.split_count.clamp(1, wallet_count.max(1)),
+        }
+    }
+
+    // the wallets of the next trade, fewer than per_trade when the pool got smaller
+    pub fn next(&self) -> Vec<Arc<Wallet>> {
+        let count = self.per_trade.min(self.wallets.len());
+        let start = self.next.fetch_add(count, Ordering::Relaxed);
+        (start..start + count).map(|index| self.wallets[index % self.wallets.len()].clone()).collect()
+    }
+
+    pub fn wallets(&self) -> &[Arc<Wallet>] {
+        &self.wallets
+    }
+
+    pub fn shares(&self) -> usize {
+        self.per_trade
+    }
+
+    pub fn retain(&mut self, keep: impl Fn(&Wallet) -> bool) {
+        self.wallets.retain(|wallet| keep(wallet));
+    }
+}
+
+// every *.json keypair in keypair_dir, by file name, or the private_key wallet without one
+pub fn load_wallets(config: &PingThingsArgs) -> anyhow::Result<Vec<Keypair>> {
+    let Some(keypair_dir) = &config.wallets.keypair_dir else {
+        return Ok(vec![Keypair::from_base58_string(&config.private_key)]);
+    };
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(keypair_dir)
+        .context(format!("cannot read keypair_dir {}", keypair_dir))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|path| path.extension().is_some_and(|extension| extension == "json"));
+    paths.sort();
+
+    let mut wallets = Vec::new();
+    for path in paths {
+        let keypair = read_keypair_file(&path)
+            .map_err(|err| anyhow::anyhow!("invalid keypair file {}: {}", path.display(), err))?;
+        wallets.push(keypair);
+    }
+    if wallets.is_empty() {
+        bail!("no keypair file in {}", keypair_dir);
+    }
+    info!("{} wallets loaded from {}", wallets.len(), keypair_dir);
+    Ok(wallets)
+}